@@ -1,18 +1,58 @@
 use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::span::Span;
 
 pub mod op;
 
+#[derive(Default, Clone, PartialEq, Eq)]
 pub struct ByteStream {
     bytes: Vec<u8>,
+    // The source span of every byte, so runtime errors can point back at the code.
+    spans: Vec<Span>,
+    span: Span,
 }
 
 impl ByteStream {
     pub fn new() -> ByteStream {
-        ByteStream { bytes: Vec::new() }
+        ByteStream { bytes: Vec::new(), spans: Vec::new(), span: Span::default() }
     }
 
     pub fn emit(&mut self, byte: u8) {
         self.bytes.push(byte);
+        self.spans.push(self.span);
+    }
+
+    pub fn emit_u16(&mut self, value: u16) {
+        for byte in value.to_be_bytes() {
+            self.emit(byte);
+        }
+    }
+
+    /// Overwrites the two bytes at `index`, used to fill in jump offsets once they are known.
+    pub fn patch_u16(&mut self, index: usize, value: u16) {
+        self.bytes[index..index + 2].copy_from_slice(&value.to_be_bytes());
+    }
+
+    /// Sets the span recorded for the bytes emitted after this call.
+    pub fn set_span(&mut self, span: Span) {
+        self.span = span;
+    }
+
+    pub fn span_at(&self, index: usize) -> Span {
+        self.spans.get(index).copied().unwrap_or_default()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
     }
 }
 
@@ -26,6 +66,7 @@ impl Iterator for ByteStream {
         let (val, remain) = self.bytes.split_at(1);
         let val = val[0];
         self.bytes = remain.to_vec();
+        self.spans.remove(0);
         Some(val)
     }
 }
@@ -39,4 +80,29 @@ impl Debug for ByteStream {
     }
 }
 
-// TODO: Make a Display implementation that shows the actual instruction names
\ No newline at end of file
+// TODO: Make a Display implementation that shows the actual instruction names
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Bool(bool),
+    UInt(u64),
+    Int(i64),
+    Float(f64),
+    String(Rc<str>),
+    Function(Rc<Function>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub arity: u8,
+    pub code: ByteStream,
+}
+
+/// A compiled program. `code` is the top-level script, and every function
+/// shares the same constant pool.
+#[derive(Debug, Default)]
+pub struct Program {
+    pub code: ByteStream,
+    pub constants: Vec<Constant>,
+}
@@ -1,4 +1,46 @@
-pub static LOAD: u8 = 0;
-pub static LOAD_CONST: u8 = 1;
-pub static STORE: u8 = 2;
-pub static INVOKE: u8 = 3;
\ No newline at end of file
+// Operands follow the opcode directly. `u8` operands are slots or argument
+// counts, `u16` operands are constant pool indices or jump offsets stored
+// big-endian. Jump offsets are relative to the end of the instruction.
+
+pub const LOAD: u8 = 0; // slot: u8
+pub const LOAD_CONST: u8 = 1; // constant: u16
+pub const STORE: u8 = 2; // slot: u8
+pub const INVOKE: u8 = 3; // argc: u8
+pub const LOAD_GLOBAL: u8 = 4; // name constant: u16
+pub const STORE_GLOBAL: u8 = 5; // name constant: u16
+pub const GET_PROPERTY: u8 = 6; // name constant: u16
+pub const UNIT: u8 = 7;
+pub const POP: u8 = 8;
+pub const RETURN: u8 = 9;
+pub const ADD: u8 = 10;
+pub const SUB: u8 = 11;
+pub const MUL: u8 = 12;
+pub const DIV: u8 = 13;
+pub const NEG: u8 = 14;
+pub const NOT: u8 = 15;
+pub const JUMP: u8 = 16; // forward offset: u16
+pub const JUMP_IF_FALSE: u8 = 17; // forward offset: u16, leaves the condition on the stack
+pub const LOOP: u8 = 18; // backward offset: u16
+// Expects the iterable and the iteration count on top of the stack. Pushes the next item and
+// bumps the count, or jumps when the iterable is exhausted.
+pub const FOR_ITER: u8 = 19; // forward offset: u16
+pub const RANGE: u8 = 20;
+pub const LIST: u8 = 21; // item count: u16
+pub const SET_PROPERTY: u8 = 22; // name constant: u16, pops the object and leaves the value
+pub const DUP: u8 = 23;
+pub const MOD: u8 = 24;
+pub const EQUAL: u8 = 25;
+pub const NOT_EQUAL: u8 = 26;
+pub const LESS: u8 = 27;
+pub const LESS_EQUAL: u8 = 28;
+pub const GREATER: u8 = 29;
+pub const GREATER_EQUAL: u8 = 30;
+pub const JUMP_IF_TRUE: u8 = 31; // forward offset: u16, leaves the condition on the stack
+pub const FORMAT: u8 = 32; // part count: u16, joins the parts as strings
+// Followed by a pair of `u8`s for each capture: 1 and a slot of the current frame, or 0 and one
+// of the current function's captures.
+pub const CLOSURE: u8 = 33; // function constant: u16, capture count: u8
+pub const LOAD_CAPTURE: u8 = 34; // capture: u8
+pub const STORE_CAPTURE: u8 = 35; // capture: u8
+// Moves every captured local in the slot and above off the stack, before it is overwritten.
+pub const CLOSE_CAPTURES: u8 = 36; // slot: u8
@@ -0,0 +1,831 @@
+use std::rc::Rc;
+
+use crate::bytecode::{op, ByteStream, Constant, Function, Program};
+use crate::lexer::token::{Token, TokenKind};
+use crate::modules::{ModuleGraph, ModuleId};
+use crate::parser::ast::*;
+use crate::source_map::SourceMap;
+use crate::span::Span;
+
+#[derive(Debug)]
+pub struct CompileError {
+    pub span: Span,
+    pub details: String,
+}
+
+/// Compiles every module in `graph` into one program, which runs their top-level code in order.
+pub fn compile(graph: &ModuleGraph, sources: &SourceMap) -> Result<Program, Vec<CompileError>> {
+    let mut compiler = Compiler::new(graph, sources);
+    for &id in graph.order() {
+        compiler.module = id;
+        // Each module numbers the locals of its top-level code from zero.
+        compiler.state_mut().slots.clear();
+        for stmt in &graph.module(id).ast {
+            compiler.statement(stmt);
+        }
+    }
+    compiler.op(op::UNIT);
+    compiler.op(op::RETURN);
+    compiler.finish()
+}
+
+// A local on the stack, which is popped when its scope ends.
+struct Local {
+    depth: usize,
+    slot: usize,
+    // Whether a closure captures it.
+    captured: bool,
+}
+
+// Where an assignment stores its value.
+enum Variable {
+    Local(u8),
+    Capture(u8),
+    Global(u16),
+}
+
+// A block expression that `return` jumps out of.
+struct BlockTarget {
+    // The stack height before the block.
+    height: usize,
+    // Jumps to the end of the block that need to be patched.
+    exits: Vec<usize>,
+}
+
+struct LoopTarget<'src> {
+    label: Option<&'src str>,
+    // The stack height inside the loop, including any slots the loop itself uses.
+    height: usize,
+    // Where `continue` jumps back to.
+    start: usize,
+    // Jumps to the end of the loop that need to be patched.
+    breaks: Vec<usize>,
+}
+
+struct FunctionState<'src> {
+    code: ByteStream,
+    locals: Vec<Local>,
+    // The stack slot of each local, by the index the resolver gave it.
+    slots: Vec<usize>,
+    depth: usize,
+    // The number of values above the frame's base, so locals declared in the middle of an
+    // expression still get the right slot.
+    height: usize,
+    blocks: Vec<BlockTarget>,
+    loops: Vec<LoopTarget<'src>>,
+}
+
+impl<'src> FunctionState<'src> {
+    fn new(depth: usize) -> FunctionState<'src> {
+        FunctionState {
+            code: ByteStream::new(),
+            locals: Vec::new(),
+            slots: Vec::new(),
+            depth,
+            height: 0,
+            blocks: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+}
+
+pub struct Compiler<'src> {
+    sources: &'src SourceMap,
+    graph: &'src ModuleGraph,
+    // The module whose code is being compiled.
+    module: ModuleId,
+    constants: Vec<Constant>,
+    // The innermost function being compiled is last. The first entry is the top-level script.
+    functions: Vec<FunctionState<'src>>,
+    errors: Vec<CompileError>,
+}
+
+impl<'src> Compiler<'src> {
+    pub fn new(graph: &'src ModuleGraph, sources: &'src SourceMap) -> Compiler<'src> {
+        Compiler {
+            sources,
+            graph,
+            module: graph.root(),
+            constants: Vec::new(),
+            functions: vec![FunctionState::new(0)],
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn finish(mut self) -> Result<Program, Vec<CompileError>> {
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        let script = self.functions.pop().expect("script state was popped");
+        Ok(Program { code: script.code, constants: self.constants })
+    }
+
+    pub fn statement(&mut self, stmt: &AbstractStatement) {
+        match stmt {
+            AbstractStatement::Expr(expr) => {
+                self.expression(expr);
+                self.op(op::POP);
+            }
+            AbstractStatement::Block(block) => self.block(block),
+            AbstractStatement::FunctionDecl(decl) => self.function_decl(decl),
+            AbstractStatement::Let(let_) => {
+                self.expression(&let_.value);
+                self.mark(let_.ident.span);
+                if self.state().depth == 0 {
+                    let name = self.global_constant(let_.ident);
+                    self.op(op::STORE_GLOBAL);
+                    self.emit_u16(name);
+                    self.op(op::POP);
+                } else {
+                    self.declare_local(let_.ident, let_.local);
+                }
+            }
+            AbstractStatement::Return(ret) => {
+                let height = self.state().height;
+                match &ret.value {
+                    Some(expr) => self.expression(expr),
+                    None => self.op(op::UNIT),
+                }
+                self.mark(ret.keyword.span);
+                match self.state().blocks.last().map(|block| block.height) {
+                    Some(target) => {
+                        self.unwind_to(target);
+                        let exit = self.emit_jump(op::JUMP);
+                        self.state_mut().blocks.last_mut().expect("no block target").exits.push(exit);
+                    }
+                    None => self.op(op::RETURN),
+                }
+                // Code after the return is unreachable, but keep the height the same as any other statement.
+                self.state_mut().height = height;
+            }
+            AbstractStatement::If(if_) => {
+                self.if_else(if_);
+                self.op(op::POP);
+            }
+            AbstractStatement::While(while_) => self.while_loop(while_),
+            AbstractStatement::For(for_) => self.for_loop(for_),
+            AbstractStatement::Break(control) | AbstractStatement::Continue(control) => self.loop_control(control),
+            // Modules and imports are resolved before compiling and don't run any code.
+            AbstractStatement::Module(_) | AbstractStatement::Use(_) => (),
+            // Parse errors have already been reported, so there is nothing to compile.
+            AbstractStatement::Error(_) => (),
+        }
+    }
+
+    pub fn expression(&mut self, expr: &AbstractExpression) {
+        match expr {
+            AbstractExpression::Grouping(inner) => self.expression(inner),
+            AbstractExpression::Binary(binary) if matches!(binary.operator.kind, TokenKind::AndAnd | TokenKind::OrOr) => {
+                // The right hand side is only evaluated if the left doesn't decide the result.
+                self.expression(&binary.lhs);
+                self.mark(binary.operator.span);
+                let skip = match binary.operator.kind {
+                    TokenKind::AndAnd => self.emit_jump(op::JUMP_IF_FALSE),
+                    _ => self.emit_jump(op::JUMP_IF_TRUE),
+                };
+                self.op(op::POP);
+                self.expression(&binary.rhs);
+                self.patch_jump(skip);
+            }
+            AbstractExpression::Binary(binary) => {
+                self.expression(&binary.lhs);
+                self.expression(&binary.rhs);
+                self.mark(binary.operator.span);
+                match binary.operator.kind {
+                    TokenKind::Plus => self.op(op::ADD),
+                    TokenKind::Minus => self.op(op::SUB),
+                    TokenKind::Star => self.op(op::MUL),
+                    TokenKind::Slash => self.op(op::DIV),
+                    TokenKind::Percent => self.op(op::MOD),
+                    TokenKind::EqEq => self.op(op::EQUAL),
+                    TokenKind::BangEq => self.op(op::NOT_EQUAL),
+                    TokenKind::Lt => self.op(op::LESS),
+                    TokenKind::LtEq => self.op(op::LESS_EQUAL),
+                    TokenKind::Gt => self.op(op::GREATER),
+                    TokenKind::GtEq => self.op(op::GREATER_EQUAL),
+                    TokenKind::DotDot => self.op(op::RANGE),
+                    _ => {
+                        self.error(binary.operator.span, String::from("Unsupported binary operator."));
+                        self.state_mut().height -= 1;
+                    }
+                }
+            }
+            AbstractExpression::Literal(literal) => {
+                let constant = match &literal.value {
+                    AbstractLiteral::Bool(value) => Constant::Bool(*value),
+                    AbstractLiteral::UInt(value) => Constant::UInt(*value),
+                    AbstractLiteral::Int(value) => Constant::Int(*value),
+                    AbstractLiteral::Float(value) => Constant::Float(*value),
+                    AbstractLiteral::String(value) => Constant::String(Rc::from(value.as_str())),
+                };
+                self.load_const(constant, literal.token.span);
+            }
+            AbstractExpression::BlockExpression(block) => {
+                let height = self.state().height;
+                self.state_mut().blocks.push(BlockTarget { height, exits: Vec::new() });
+                self.block_value(block);
+                let target = self.state_mut().blocks.pop().expect("no block target");
+                for exit in target.exits {
+                    self.patch_jump(exit);
+                }
+                self.state_mut().height = height + 1;
+            }
+            AbstractExpression::TrailingBlock(block) => self.block_value(block),
+            AbstractExpression::PropertyAccess(access) => match &access.obj {
+                Some(obj) => {
+                    self.expression(obj);
+                    self.mark(access.property.span);
+                    let name = self.name_constant(access.property);
+                    self.op(op::GET_PROPERTY);
+                    self.emit_u16(name);
+                }
+                None => self.load_name(access),
+            },
+            AbstractExpression::Unary(unary) => {
+                self.expression(&unary.expr);
+                self.mark(unary.op.span);
+                match unary.op.kind {
+                    TokenKind::Minus => self.op(op::NEG),
+                    TokenKind::Bang => self.op(op::NOT),
+                    _ => self.error(unary.op.span, String::from("Unsupported unary operator.")),
+                }
+            }
+            AbstractExpression::Call(call) => {
+                self.expression(&call.expr);
+                for arg in &call.args {
+                    self.expression(arg);
+                }
+                let span = call.expr.span().unwrap_or_default();
+                self.mark(span);
+                match u8::try_from(call.args.len()) {
+                    Ok(argc) => {
+                        self.op(op::INVOKE);
+                        self.emit(argc);
+                        self.state_mut().height -= argc as usize;
+                    }
+                    Err(_) => {
+                        self.error(span, String::from("Too many arguments in call."));
+                        self.state_mut().height -= call.args.len();
+                    }
+                }
+            }
+            AbstractExpression::If(if_) => self.if_else(if_),
+            AbstractExpression::List(list) => {
+                for item in &list.items {
+                    self.expression(item);
+                }
+                self.mark(list.bracket.span);
+                match u16::try_from(list.items.len()) {
+                    Ok(count) => {
+                        self.op(op::LIST);
+                        self.emit_u16(count);
+                        self.state_mut().height -= count as usize;
+                    }
+                    Err(_) => {
+                        self.error(list.bracket.span, String::from("Too many items in list."));
+                        self.state_mut().height -= list.items.len() - 1;
+                    }
+                }
+            }
+            AbstractExpression::Assign(assign) => self.assign(assign),
+            AbstractExpression::Format(format) => {
+                for part in &format.parts {
+                    match part {
+                        FormatPart::Text(text) => self.load_const(Constant::String(Rc::from(text.as_str())), format.string.span),
+                        FormatPart::Expr(expr) => self.expression(expr),
+                    }
+                }
+                self.mark(format.string.span);
+                match u16::try_from(format.parts.len()) {
+                    Ok(count) => {
+                        self.op(op::FORMAT);
+                        self.emit_u16(count);
+                        self.state_mut().height -= count as usize;
+                    }
+                    Err(_) => {
+                        self.error(format.string.span, String::from("Too many interpolations in string."));
+                        self.state_mut().height -= format.parts.len() - 1;
+                    }
+                }
+            }
+            AbstractExpression::Path(path) => {
+                self.mark(path.span());
+                match self.graph.path(path) {
+                    Some(qualified) => {
+                        let name = self.constant(Constant::String(Rc::from(qualified)), path.span());
+                        self.op(op::LOAD_GLOBAL);
+                        self.emit_u16(name);
+                    }
+                    None => {
+                        let text = self.sources.span_str(path.span());
+                        self.error(path.span(), format!("Cannot find `{}`.", text));
+                        // A placeholder, so the stack height stays consistent.
+                        self.op(op::UNIT);
+                    }
+                }
+            }
+        }
+    }
+
+    fn assign(&mut self, assign: &Assign) {
+        let operator = match assign.operator.kind {
+            TokenKind::PlusEq => Some(op::ADD),
+            TokenKind::MinusEq => Some(op::SUB),
+            TokenKind::StarEq => Some(op::MUL),
+            TokenKind::SlashEq => Some(op::DIV),
+            _ => None,
+        };
+        let target = &assign.target;
+        match &target.obj {
+            Some(obj) => {
+                self.expression(obj);
+                let name = self.name_constant(target.property);
+                if let Some(instruction) = operator {
+                    self.op(op::DUP);
+                    self.mark(target.property.span);
+                    self.op(op::GET_PROPERTY);
+                    self.emit_u16(name);
+                    self.expression(&assign.value);
+                    self.mark(assign.operator.span);
+                    self.op(instruction);
+                } else {
+                    self.expression(&assign.value);
+                }
+                self.mark(assign.operator.span);
+                self.op(op::SET_PROPERTY);
+                self.emit_u16(name);
+            }
+            None => {
+                let Some(variable) = self.resolve_assignment(target) else {
+                    // Still compile the value so the stack height stays consistent.
+                    self.expression(&assign.value);
+                    return;
+                };
+                if let Some(instruction) = operator {
+                    self.load_name(target);
+                    self.expression(&assign.value);
+                    self.mark(assign.operator.span);
+                    self.op(instruction);
+                } else {
+                    self.expression(&assign.value);
+                }
+                self.mark(assign.operator.span);
+                match variable {
+                    Variable::Local(slot) => {
+                        self.op(op::STORE);
+                        self.emit(slot);
+                    }
+                    Variable::Capture(index) => {
+                        self.op(op::STORE_CAPTURE);
+                        self.emit(index);
+                    }
+                    Variable::Global(name) => {
+                        self.op(op::STORE_GLOBAL);
+                        self.emit_u16(name);
+                    }
+                }
+            }
+        }
+    }
+
+    // Finds the variable a name refers to. The resolver has already checked it can be assigned to.
+    fn resolve_assignment(&mut self, target: &PropertyAccess) -> Option<Variable> {
+        let ident = target.property;
+        match &target.resolution {
+            Some(Resolution::Local(index)) => Some(Variable::Local(self.slot(*index) as u8)),
+            Some(Resolution::Capture(index)) => Some(Variable::Capture(*index as u8)),
+            Some(Resolution::Global(qualified)) => Some(Variable::Global(self.constant(Constant::String(Rc::from(qualified.as_str())), ident.span))),
+            Some(Resolution::Builtin) | None => {
+                let name = self.name(ident);
+                self.error(ident.span, format!("Cannot assign to `{}`.", name));
+                None
+            }
+        }
+    }
+
+    fn while_loop(&mut self, while_: &While) {
+        let height = self.state().height;
+        let start = self.state().code.len();
+        self.expression(&while_.condition);
+        self.mark(while_.keyword.span);
+        let exit = self.emit_jump(op::JUMP_IF_FALSE);
+        self.op(op::POP);
+
+        let label = while_.label.map(|label| self.name(label));
+        self.state_mut().loops.push(LoopTarget { label, height, start, breaks: Vec::new() });
+        self.block(&while_.body);
+        self.emit_loop(start);
+
+        self.patch_jump(exit);
+        self.state_mut().height = height + 1;
+        self.op(op::POP);
+        let target = self.state_mut().loops.pop().expect("no loop target");
+        for exit in target.breaks {
+            self.patch_jump(exit);
+        }
+    }
+
+    fn for_loop(&mut self, for_: &For) {
+        // The iterable and the number of items taken from it live in two hidden slots.
+        let height = self.state().height;
+        self.expression(&for_.iterable);
+        self.load_const(Constant::UInt(0), for_.keyword.span);
+        let start = self.state().code.len();
+        self.mark(for_.keyword.span);
+        let exit = self.emit_jump(op::FOR_ITER);
+        self.state_mut().height += 1;
+
+        self.begin_scope();
+        self.declare_local(for_.binding, for_.local);
+        let label = for_.label.map(|label| self.name(label));
+        self.state_mut().loops.push(LoopTarget { label, height: height + 2, start, breaks: Vec::new() });
+        self.block(&for_.body);
+        self.end_scope();
+        self.emit_loop(start);
+
+        self.patch_jump(exit);
+        self.state_mut().height = height + 2;
+        let target = self.state_mut().loops.pop().expect("no loop target");
+        for exit in target.breaks {
+            self.patch_jump(exit);
+        }
+        self.op(op::POP);
+        self.op(op::POP);
+    }
+
+    fn loop_control(&mut self, control: &LoopControl) {
+        let label = control.label.map(|label| self.name(label));
+        let state = self.state();
+        let Some(index) = state.loops.iter().rposition(|target| label.is_none() || target.label == label) else {
+            self.error(control.keyword.span, format!("Cannot use '{}' outside of a loop.", control.keyword.kind));
+            return;
+        };
+        let (target_height, start) = (state.loops[index].height, state.loops[index].start);
+
+        let height = state.height;
+        self.mark(control.keyword.span);
+        for _ in target_height..height {
+            self.op(op::POP);
+        }
+        if control.keyword.kind == TokenKind::Break {
+            let exit = self.emit_jump(op::JUMP);
+            self.state_mut().loops[index].breaks.push(exit);
+        } else {
+            self.emit_loop(start);
+        }
+        // Code after the jump is unreachable, but keep the height the same as any other statement.
+        self.state_mut().height = height;
+    }
+
+    fn if_else(&mut self, if_: &If) {
+        let height = self.state().height;
+        self.expression(&if_.condition);
+        self.mark(if_.keyword.span);
+        let otherwise = self.emit_jump(op::JUMP_IF_FALSE);
+        self.op(op::POP);
+        self.block_value(&if_.then);
+        let end = self.emit_jump(op::JUMP);
+
+        self.patch_jump(otherwise);
+        self.state_mut().height = height + 1;
+        self.op(op::POP);
+        match &if_.otherwise {
+            Some(Else::If(nested)) => self.if_else(nested),
+            Some(Else::Block(block)) => self.block_value(block),
+            None => self.op(op::UNIT),
+        }
+        self.patch_jump(end);
+    }
+
+    // Compiles `block` in a new scope, leaving only its value on the stack.
+    fn block_value(&mut self, block: &Block) {
+        let height = self.state().height;
+        self.begin_scope();
+        for stmt in &block.stmts {
+            self.statement(stmt);
+        }
+        match &block.value {
+            Some(value) => self.expression(value),
+            None => self.op(op::UNIT),
+        }
+        self.unwind_to(height);
+        self.discard_scope();
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.begin_scope();
+        for stmt in &block.stmts {
+            self.statement(stmt);
+        }
+        if let Some(value) = &block.value {
+            self.expression(value);
+            self.op(op::POP);
+        }
+        self.end_scope();
+    }
+
+    fn function_decl(&mut self, decl: &FunctionDecl) {
+        let name = self.name(decl.ident);
+        let Ok(arity) = u8::try_from(decl.arguments.len()) else {
+            self.error(decl.ident.span, format!("Function `{}` has too many parameters.", name));
+            return;
+        };
+
+        self.functions.push(FunctionState::new(1));
+        self.mark(decl.ident.span);
+        for (index, arg) in decl.arguments.iter().enumerate() {
+            self.state_mut().height += 1;
+            self.declare_local(arg.ident, Some(index));
+        }
+        for stmt in &decl.body.stmts {
+            self.statement(stmt);
+        }
+        match &decl.body.value {
+            Some(value) => self.expression(value),
+            None => self.op(op::UNIT),
+        }
+        self.op(op::RETURN);
+        let state = self.functions.pop().expect("function state was popped");
+
+        let name = match self.state().depth {
+            0 => self.graph.module(self.module).qualify(name),
+            _ => String::from(name),
+        };
+        let function = Function { name, arity, code: state.code };
+        self.mark(decl.ident.span);
+        if decl.captures.is_empty() {
+            self.load_const(Constant::Function(Rc::new(function)), decl.ident.span);
+        } else {
+            self.closure(function, decl);
+        }
+        if self.state().depth == 0 {
+            let name = self.global_constant(decl.ident);
+            self.op(op::STORE_GLOBAL);
+            self.emit_u16(name);
+            self.op(op::POP);
+        } else {
+            // The function value stays on the stack as the local's slot.
+            self.declare_local(decl.ident, decl.local);
+            if decl.local.is_some_and(|index| decl.captures.contains(&Capture::Local(index))) {
+                let slot = self.state().height - 1;
+                self.capture_slot(slot);
+            }
+        }
+    }
+
+    // Pushes a closure of `function` over the locals `decl` captures from enclosing functions.
+    fn closure(&mut self, function: Function, decl: &FunctionDecl) {
+        let Ok(count) = u8::try_from(decl.captures.len()) else {
+            self.error(decl.ident.span, format!("Function `{}` captures too many variables.", function.name));
+            // A placeholder, so the stack height stays consistent.
+            self.op(op::UNIT);
+            return;
+        };
+        // A function that calls itself captures the slot it's about to be stored in.
+        if let Some(index) = decl.local {
+            let height = self.state().height;
+            self.set_slot(index, height);
+        }
+        let constant = self.constant(Constant::Function(Rc::new(function)), decl.ident.span);
+        self.op(op::CLOSURE);
+        self.emit_u16(constant);
+        self.emit(count);
+        for capture in &decl.captures {
+            match *capture {
+                Capture::Local(index) => {
+                    let slot = self.slot(index);
+                    self.capture_slot(slot);
+                    self.emit(1);
+                    self.emit(slot as u8);
+                }
+                Capture::Enclosing(index) => {
+                    self.emit(0);
+                    self.emit(index as u8);
+                }
+            }
+        }
+    }
+
+    // Marks the local in `slot` as captured, if it has been declared yet.
+    fn capture_slot(&mut self, slot: usize) {
+        if let Some(local) = self.state_mut().locals.iter_mut().rev().find(|local| local.slot == slot) {
+            local.captured = true;
+        }
+    }
+
+    fn load_name(&mut self, access: &PropertyAccess) {
+        let ident = access.property;
+        self.mark(ident.span);
+        let name = match &access.resolution {
+            Some(Resolution::Local(index)) => {
+                let slot = self.slot(*index);
+                self.op(op::LOAD);
+                self.emit(slot as u8);
+                return;
+            }
+            Some(Resolution::Capture(index)) => {
+                self.op(op::LOAD_CAPTURE);
+                self.emit(*index as u8);
+                return;
+            }
+            Some(Resolution::Global(qualified)) => qualified.as_str(),
+            Some(Resolution::Builtin) | None => self.name(ident),
+        };
+        let name = self.constant(Constant::String(Rc::from(name)), ident.span);
+        self.op(op::LOAD_GLOBAL);
+        self.emit_u16(name);
+    }
+
+    // The name a top-level declaration in the current module is stored under.
+    fn global_constant(&mut self, ident: Token) -> u16 {
+        let name = self.graph.module(self.module).qualify(self.name(ident));
+        self.constant(Constant::String(Rc::from(name)), ident.span)
+    }
+
+    // Declares the value on top of the stack as the local the resolver numbered `index`.
+    fn declare_local(&mut self, ident: Token, index: Option<usize>) {
+        let state = self.state_mut();
+        let slot = state.height - 1;
+        if slot > u8::MAX as usize {
+            self.error(ident.span, String::from("Too many local variables in function."));
+            return;
+        }
+        let depth = state.depth;
+        state.locals.push(Local { depth, slot, captured: false });
+        if let Some(index) = index {
+            self.set_slot(index, slot);
+        }
+    }
+
+    fn set_slot(&mut self, index: usize, slot: usize) {
+        let slots = &mut self.state_mut().slots;
+        if slots.len() <= index {
+            slots.resize(index + 1, 0);
+        }
+        slots[index] = slot;
+    }
+
+    fn slot(&self, index: usize) -> usize {
+        self.state().slots[index]
+    }
+
+    fn begin_scope(&mut self) {
+        self.state_mut().depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.state_mut();
+        state.depth -= 1;
+        while state.locals.last().is_some_and(|local| local.depth > state.depth) {
+            state.locals.pop();
+            state.code.emit(op::POP);
+            state.height -= 1;
+        }
+    }
+
+    // Ends a scope whose locals have already been removed from the stack.
+    fn discard_scope(&mut self) {
+        let state = self.state_mut();
+        state.depth -= 1;
+        while state.locals.last().is_some_and(|local| local.depth > state.depth) {
+            state.locals.pop();
+        }
+    }
+
+    // Moves the value on top of the stack down to `height`, dropping everything between them.
+    fn unwind_to(&mut self, height: usize) {
+        let extra = self.state().height - height - 1;
+        if extra == 0 {
+            return;
+        }
+        let Ok(slot) = u8::try_from(height) else {
+            let span = self.state().code.span_at(self.state().code.len().saturating_sub(1));
+            self.error(span, String::from("Too many values on the stack."));
+            return;
+        };
+        if self.state().locals.iter().any(|local| local.captured && local.slot >= height) {
+            self.op(op::CLOSE_CAPTURES);
+            self.emit(slot);
+        }
+        self.op(op::STORE);
+        self.emit(slot);
+        for _ in 0..extra {
+            self.op(op::POP);
+        }
+    }
+
+    fn emit_jump(&mut self, instruction: u8) -> usize {
+        self.emit(instruction);
+        self.emit_u16(0);
+        self.state().code.len() - 2
+    }
+
+    fn emit_loop(&mut self, start: usize) {
+        self.op(op::LOOP);
+        let offset = self.state().code.len() + 2 - start;
+        match u16::try_from(offset) {
+            Ok(offset) => self.emit_u16(offset),
+            Err(_) => {
+                let span = self.state().code.span_at(start);
+                self.error(span, String::from("Loop body is too large."));
+            }
+        }
+    }
+
+    fn patch_jump(&mut self, operand: usize) {
+        let offset = self.state().code.len() - operand - 2;
+        match u16::try_from(offset) {
+            Ok(offset) => self.state_mut().code.patch_u16(operand, offset),
+            Err(_) => {
+                let span = self.state().code.span_at(operand);
+                self.error(span, String::from("Too much code to jump over."));
+            }
+        }
+    }
+
+    fn name(&self, ident: Token) -> &'src str {
+        self.sources.span_str(ident.span)
+    }
+
+    fn name_constant(&mut self, ident: Token) -> u16 {
+        let name = self.name(ident);
+        self.constant(Constant::String(Rc::from(name)), ident.span)
+    }
+
+    fn load_const(&mut self, constant: Constant, span: Span) {
+        let index = self.constant(constant, span);
+        self.op(op::LOAD_CONST);
+        self.emit_u16(index);
+    }
+
+    fn constant(&mut self, constant: Constant, span: Span) -> u16 {
+        let existing = match constant {
+            Constant::Function(_) => None,
+            _ => self.constants.iter().position(|other| *other == constant),
+        };
+        let index = existing.unwrap_or_else(|| {
+            self.constants.push(constant);
+            self.constants.len() - 1
+        });
+        u16::try_from(index).unwrap_or_else(|_| {
+            self.error(span, String::from("Too many constants in program."));
+            0
+        })
+    }
+
+    fn mark(&mut self, span: Span) {
+        self.state_mut().code.set_span(span);
+    }
+
+    fn op(&mut self, instruction: u8) {
+        let state = self.state_mut();
+        state.code.emit(instruction);
+        match instruction {
+            op::LOAD
+            | op::LOAD_CONST
+            | op::LOAD_GLOBAL
+            | op::LOAD_CAPTURE
+            | op::CLOSURE
+            | op::UNIT
+            | op::LIST
+            | op::FORMAT
+            | op::DUP => state.height += 1,
+            op::POP
+            | op::RETURN
+            | op::ADD
+            | op::SUB
+            | op::MUL
+            | op::DIV
+            | op::MOD
+            | op::EQUAL
+            | op::NOT_EQUAL
+            | op::LESS
+            | op::LESS_EQUAL
+            | op::GREATER
+            | op::GREATER_EQUAL
+            | op::RANGE
+            | op::SET_PROPERTY => state.height -= 1,
+            _ => (),
+        }
+    }
+
+    fn emit(&mut self, byte: u8) {
+        self.state_mut().code.emit(byte);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        self.state_mut().code.emit_u16(value);
+    }
+
+    fn state(&self) -> &FunctionState<'src> {
+        self.functions.last().expect("no function state")
+    }
+
+    fn state_mut(&mut self) -> &mut FunctionState<'src> {
+        self.functions.last_mut().expect("no function state")
+    }
+
+    fn error(&mut self, span: Span, details: String) {
+        self.errors.push(CompileError { span, details });
+    }
+}
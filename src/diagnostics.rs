@@ -0,0 +1,286 @@
+use std::fmt::{Display, Write};
+
+use crate::compiler::CompileError;
+use crate::lexer::lex::LexError;
+use crate::modules::ResolveError;
+use crate::parser::ParseError;
+use crate::source_map::SourceMap;
+use crate::span::{FileId, FileIndex, Span};
+use crate::types::TypeError;
+use crate::vm::RuntimeError;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn colour(self) -> &'static str {
+        match self {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+            Severity::Note => "\x1b[1;32m",
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Note => "note",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            primary: Label { span, message: String::new() },
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic::new(Severity::Error, message, span)
+    }
+
+    /// Sets the text printed next to the primary span's carets.
+    pub fn with_label(mut self, message: impl Into<String>) -> Diagnostic {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.secondary.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help.push(help.into());
+        self
+    }
+
+    /// Renders the diagnostic against the files in `sources`, optionally with ANSI colours.
+    pub fn render(&self, sources: &SourceMap, colour: bool) -> String {
+        let paint = |code: &'static str| if colour { code } else { "" };
+        let reset = paint(RESET);
+
+        // Primary label first so its carets are drawn above any secondary ones on the same line.
+        // Labels in files the map doesn't have, like the default span of a host call, aren't shown.
+        let labels: Vec<Located> = [(&self.primary, true)]
+            .into_iter()
+            .chain(self.secondary.iter().map(|label| (label, false)))
+            .filter(|(label, _)| sources.get(label.span.file).is_some())
+            .map(|(label, primary)| Located::new(label, primary, sources))
+            .collect();
+
+        // Each file gets its own snippet, in the order its first label appears.
+        let mut files: Vec<FileId> = vec![];
+        for label in &labels {
+            if !files.contains(&label.label.span.file) {
+                files.push(label.label.span.file);
+            }
+        }
+
+        let gutter = labels.iter().map(|label| (label.lines().1 + 1).to_string().len()).max().unwrap_or(1);
+        let pad = " ".repeat(gutter);
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{}{}{}{}: {}{}", paint(self.severity.colour()), self.severity, reset, paint(BOLD), self.message, reset);
+
+        for (i, &file) in files.iter().enumerate() {
+            let source = sources.file(file);
+            let labels: Vec<&Located> = labels.iter().filter(|label| label.label.span.file == file).collect();
+            let mut shown: Vec<usize> = labels
+                .iter()
+                .flat_map(|label| {
+                    let (first, last) = label.lines();
+                    first..=last
+                })
+                .filter(|line| *line < source.line_count())
+                .collect();
+            shown.sort_unstable();
+            shown.dedup();
+
+            let start = labels[0].start;
+            let arrow = if i == 0 { "-->" } else { ":::" };
+            let _ = writeln!(out, "{}{}{}{} {}:{}:{}", pad, paint(BLUE), arrow, reset, source.name(), start.line + 1, start.column + 1);
+            let _ = writeln!(out, "{} {}|{}", pad, paint(BLUE), reset);
+
+            let mut previous: Option<usize> = None;
+            for &line_no in &shown {
+                if previous.is_some_and(|previous| line_no > previous + 1) {
+                    let _ = writeln!(out, "{}{}...{}", " ".repeat(gutter.saturating_sub(2)), paint(BLUE), reset);
+                }
+                previous = Some(line_no);
+
+                let line = source.line(line_no);
+                let _ = writeln!(out, "{}{:>gutter$} |{} {}", paint(BLUE), line_no + 1, reset, line);
+
+                for label in &labels {
+                    let Some((from, to)) = label.underline(line_no, line) else {
+                        continue;
+                    };
+                    let (marker, code) = if label.primary { ('^', self.severity.colour()) } else { ('-', BLUE) };
+                    let indent: String = line.chars().take(from).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+                    let is_last = label.lines().1 == line_no;
+                    let message = if is_last && !label.label.message.is_empty() { format!(" {}", label.label.message) } else { String::new() };
+                    let _ = writeln!(
+                        out,
+                        "{} {}|{} {}{}{}{}{}",
+                        pad,
+                        paint(BLUE),
+                        reset,
+                        indent,
+                        paint(code),
+                        marker.to_string().repeat(to - from),
+                        message,
+                        reset
+                    );
+                }
+            }
+        }
+
+        if !self.notes.is_empty() || !self.help.is_empty() {
+            let _ = writeln!(out, "{} {}|{}", pad, paint(BLUE), reset);
+        }
+        for note in &self.notes {
+            let _ = writeln!(out, "{} {}={} {}note{}: {}", pad, paint(BLUE), reset, paint(BOLD), reset, note);
+        }
+        for help in &self.help {
+            let _ = writeln!(out, "{} {}={} {}help{}: {}", pad, paint(BLUE), reset, paint(CYAN), reset, help);
+        }
+        out
+    }
+}
+
+// A label with its span looked up in the source map.
+struct Located<'a> {
+    label: &'a Label,
+    primary: bool,
+    start: FileIndex,
+    end: FileIndex,
+}
+
+impl<'a> Located<'a> {
+    fn new(label: &'a Label, primary: bool, sources: &SourceMap) -> Located<'a> {
+        Located { label, primary, start: sources.start(label.span), end: sources.end(label.span) }
+    }
+
+    // The first and last line the label covers. A span that ends at the start of a line doesn't cover it.
+    fn lines(&self) -> (usize, usize) {
+        if self.end.line > self.start.line && self.end.column == 0 {
+            (self.start.line, self.end.line - 1)
+        } else {
+            (self.start.line, self.end.line.max(self.start.line))
+        }
+    }
+
+    // The columns of `line` that the label covers, or None if it doesn't touch the line.
+    fn underline(&self, line_no: usize, line: &str) -> Option<(usize, usize)> {
+        let (first, last) = self.lines();
+        if line_no < first || line_no > last {
+            return None;
+        }
+        let len = line.chars().count();
+        let from = if line_no == first { self.start.column } else { 0 };
+        let to = if line_no == last && self.end.line == line_no { self.end.column } else { len };
+        // Empty spans, like the end of the input, still get a single caret.
+        Some((from, to.max(from + 1)))
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(error: &LexError) -> Self {
+        Diagnostic::error(error.details.clone(), error.span)
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let found = match error.found {
+            Some(kind) => format!("found `{}`", kind),
+            None => String::from("found end of input"),
+        };
+        let diagnostic = Diagnostic::error(error.details.clone(), error.span);
+        match error.expected.as_slice() {
+            // Errors that aren't about an unexpected token don't need to repeat it.
+            [] => diagnostic,
+            [kind] => diagnostic.with_label(found).with_note(format!("expected `{}`", kind)),
+            kinds => {
+                let kinds: Vec<String> = kinds.iter().map(|kind| format!("`{}`", kind)).collect();
+                diagnostic.with_label(found).with_note(format!("expected one of {}", kinds.join(", ")))
+            }
+        }
+    }
+}
+
+impl From<&ResolveError> for Diagnostic {
+    fn from(error: &ResolveError) -> Self {
+        let mut diagnostic = Diagnostic::error(error.details.clone(), error.span);
+        for (span, message) in &error.related {
+            diagnostic = diagnostic.with_secondary(*span, message.clone());
+        }
+        diagnostic
+    }
+}
+
+impl From<&TypeError> for Diagnostic {
+    fn from(error: &TypeError) -> Self {
+        let mut diagnostic = Diagnostic::error(error.details.clone(), error.span);
+        for (span, message) in &error.related {
+            diagnostic = diagnostic.with_secondary(*span, message.clone());
+        }
+        diagnostic
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(error: &CompileError) -> Self {
+        Diagnostic::error(error.details.clone(), error.span)
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(error: &RuntimeError) -> Self {
+        Diagnostic::error(error.details.clone(), error.span)
+    }
+}
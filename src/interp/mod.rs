@@ -0,0 +1,524 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::lexer::token::{Token, TokenKind};
+use crate::modules::{ModuleGraph, ModuleId};
+use crate::parser::ast::*;
+use crate::source_map::SourceMap;
+use crate::span::Span;
+use crate::value::{self, BinaryOp, Callable, Native};
+use crate::vm::RuntimeError;
+
+// Each call takes several kilobytes of native stack in debug builds, so this keeps even nested
+// recursion inside the 2 MiB that spawned threads get by default.
+const MAX_DEPTH: usize = 128;
+
+pub type Value = value::Value<Closure>;
+
+#[derive(Debug)]
+pub struct Closure {
+    pub name: String,
+    params: Vec<String>,
+    body: Rc<Block>,
+    env: Env,
+    // The module the function is declared in, which its globals are looked up in.
+    module: ModuleId,
+}
+
+impl Callable for Closure {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+pub type Result<T> = std::result::Result<T, RuntimeError>;
+
+type Env = Rc<RefCell<Environment>>;
+
+#[derive(Debug)]
+struct Binding {
+    value: Value,
+    mutable: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<String, Binding>,
+    parent: Option<Env>,
+}
+
+impl Environment {
+    fn child(parent: &Env) -> Env {
+        Rc::new(RefCell::new(Environment { values: HashMap::new(), parent: Some(Rc::clone(parent)) }))
+    }
+
+    fn get(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(binding) => Some(binding.value.clone()),
+            None => self.parent.as_ref()?.borrow().get(name),
+        }
+    }
+
+    // Like `get`, but globals are looked up by their qualified name `global` instead.
+    fn lookup(&self, name: &str, global: &str) -> Option<Value> {
+        match &self.parent {
+            Some(parent) => match self.values.get(name) {
+                Some(binding) => Some(binding.value.clone()),
+                None => parent.borrow().lookup(name, global),
+            },
+            None => self.values.get(global).map(|binding| binding.value.clone()),
+        }
+    }
+
+    fn define(&mut self, name: String, value: Value, mutable: bool) {
+        self.values.insert(name, Binding { value, mutable });
+    }
+
+    // Replaces the value of the nearest binding called `name`, or the global called `global`.
+    fn assign(&mut self, name: &str, global: &str, value: Value) -> std::result::Result<(), String> {
+        let key = if self.parent.is_some() { name } else { global };
+        match self.values.get_mut(key) {
+            Some(binding) if binding.mutable => {
+                binding.value = value;
+                Ok(())
+            }
+            Some(_) => Err(format!("Cannot assign to immutable variable `{}`.", name)),
+            None => match &self.parent {
+                Some(parent) => parent.borrow_mut().assign(name, global, value),
+                None => Err(format!("Cannot assign to undefined name `{}`.", name)),
+            },
+        }
+    }
+}
+
+// Statements either complete normally or unwind to the nearest function call or loop.
+enum Unwind {
+    Return(Value),
+    // The label of the loop being exited, or None for the innermost loop.
+    Break(Option<String>),
+    Continue(Option<String>),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+type Exec = std::result::Result<(), Unwind>;
+
+pub struct Interpreter<'src> {
+    sources: &'src SourceMap,
+    graph: &'src ModuleGraph,
+    // The module whose code is running.
+    module: ModuleId,
+    globals: Env,
+    depth: usize,
+    // Where natives print to.
+    output: Box<dyn Write>,
+}
+
+impl<'src> Interpreter<'src> {
+    pub fn new(graph: &'src ModuleGraph, sources: &'src SourceMap) -> Interpreter<'src> {
+        let mut globals = Environment::default();
+        for native in Native::ALL {
+            globals.define(String::from(native.name()), Value::Native(native), false);
+        }
+        Interpreter {
+            sources,
+            graph,
+            module: graph.root(),
+            globals: Rc::new(RefCell::new(globals)),
+            depth: 0,
+            output: Box::new(std::io::stdout()),
+        }
+    }
+
+    /// Sends everything the program prints to `output` instead of stdout.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Runs every module and then the root module's `main` function, if it defines one.
+    pub fn run(&mut self) -> Result<Value> {
+        let value = self.execute()?;
+        if self.global("main").is_some() {
+            self.call("main", vec![])
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Runs the top-level statements of every module, leaving their globals defined in the
+    /// interpreter. Returns the value the root module returns, if it returns one.
+    pub fn execute(&mut self) -> Result<Value> {
+        let mut result = Value::Unit;
+        for &id in self.graph.order() {
+            self.module = id;
+            let value = self.execute_module(id)?;
+            if id == self.graph.root() {
+                result = value;
+            }
+        }
+        self.module = self.graph.root();
+        Ok(result)
+    }
+
+    fn execute_module(&mut self, id: ModuleId) -> Result<Value> {
+        let env = Rc::clone(&self.globals);
+        for stmt in &self.graph.module(id).ast {
+            match self.statement(stmt, &env) {
+                Ok(()) => (),
+                Err(Unwind::Return(value)) => return Ok(value),
+                Err(Unwind::Error(error)) => return Err(error),
+                // The parser rejects `break` and `continue` outside of loops.
+                Err(Unwind::Break(_) | Unwind::Continue(_)) => unreachable!("loop control outside of a loop"),
+            }
+        }
+        Ok(Value::Unit)
+    }
+
+    /// Calls the global function `name` with `args`.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let callee = self.global(name).ok_or_else(|| RuntimeError {
+            span: Span::default(),
+            details: format!("Undefined name `{}`.", name),
+        })?;
+        self.invoke(callee, args, Span::default())
+    }
+
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get(name)
+    }
+
+    fn statement(&mut self, stmt: &AbstractStatement, env: &Env) -> Exec {
+        match stmt {
+            AbstractStatement::Expr(expr) => {
+                self.expression(expr, env)?;
+            }
+            AbstractStatement::Block(block) => {
+                self.block(block, &Environment::child(env))?;
+            }
+            AbstractStatement::FunctionDecl(decl) => {
+                let name = self.definition(decl.ident, env);
+                let closure = Closure {
+                    name: name.clone(),
+                    params: decl.arguments.iter().map(|arg| String::from(self.name(arg.ident))).collect(),
+                    body: Rc::new(decl.body.clone()),
+                    env: Rc::clone(env),
+                    module: self.module,
+                };
+                env.borrow_mut().define(name, Value::Function(Rc::new(closure)), false);
+            }
+            AbstractStatement::Let(let_) => {
+                let value = self.expression(&let_.value, env)?;
+                let name = self.definition(let_.ident, env);
+                env.borrow_mut().define(name, value, let_.mutable);
+            }
+            AbstractStatement::Return(ret) => {
+                let value = match &ret.value {
+                    Some(expr) => self.expression(expr, env)?,
+                    None => Value::Unit,
+                };
+                return Err(Unwind::Return(value));
+            }
+            AbstractStatement::If(if_) => {
+                self.if_else(if_, env)?;
+            }
+            AbstractStatement::While(while_) => {
+                let label = while_.label.map(|label| self.label(label));
+                loop {
+                    let condition = self.expression(&while_.condition, env)?;
+                    let holds = condition.is_truthy();
+                    if !holds || !self.loop_body(&while_.body, label, &Environment::child(env))? {
+                        break;
+                    }
+                }
+            }
+            AbstractStatement::For(for_) => {
+                let label = for_.label.map(|label| self.label(label));
+                let iterable = self.expression(&for_.iterable, env)?;
+                let mut index = 0;
+                while let Some(item) = iterable
+                    .iterate(index)
+                    .map_err(|details| RuntimeError { span: for_.keyword.span, details })?
+                {
+                    let scope = Environment::child(env);
+                    scope.borrow_mut().define(String::from(self.name(for_.binding)), item, false);
+                    if !self.loop_body(&for_.body, label, &scope)? {
+                        break;
+                    }
+                    index += 1;
+                }
+            }
+            AbstractStatement::Break(control) => {
+                return Err(Unwind::Break(control.label.map(|label| String::from(self.label(label)))));
+            }
+            AbstractStatement::Continue(control) => {
+                return Err(Unwind::Continue(control.label.map(|label| String::from(self.label(label)))));
+            }
+            // Modules and imports are resolved before running and don't do anything at runtime.
+            AbstractStatement::Module(_) | AbstractStatement::Use(_) => (),
+            // Parse errors have already been reported, so there is nothing to run.
+            AbstractStatement::Error(_) => (),
+        }
+        Ok(())
+    }
+
+    // Runs the statements of `block` in `env` and evaluates its trailing value, if it has one.
+    fn block(&mut self, block: &Block, env: &Env) -> std::result::Result<Value, Unwind> {
+        for stmt in &block.stmts {
+            self.statement(stmt, env)?;
+        }
+        match &block.value {
+            Some(value) => self.expression(value, env),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    // Runs one iteration of a loop labelled `label`, returning whether the loop should keep going.
+    fn loop_body(&mut self, body: &Block, label: Option<&str>, env: &Env) -> std::result::Result<bool, Unwind> {
+        match self.block(body, env) {
+            Ok(_) => Ok(true),
+            Err(Unwind::Break(target)) if target.is_none() || target.as_deref() == label => Ok(false),
+            Err(Unwind::Continue(target)) if target.is_none() || target.as_deref() == label => Ok(true),
+            Err(unwind) => Err(unwind),
+        }
+    }
+
+    // Each kind of expression is evaluated by its own method, which keeps this frame small since
+    // every call in the program recurses through it.
+    fn expression(&mut self, expr: &AbstractExpression, env: &Env) -> std::result::Result<Value, Unwind> {
+        match expr {
+            AbstractExpression::Grouping(inner) => self.expression(inner, env),
+            AbstractExpression::Binary(binary) => self.binary_expression(binary, env),
+            AbstractExpression::Literal(literal) => Ok(match &literal.value {
+                AbstractLiteral::Bool(value) => Value::Bool(*value),
+                AbstractLiteral::UInt(value) => Value::UInt(*value),
+                AbstractLiteral::Int(value) => Value::Int(*value),
+                AbstractLiteral::Float(value) => Value::Float(*value),
+                AbstractLiteral::String(value) => Value::String(Rc::from(value.as_str())),
+            }),
+            // A `return` inside a block expression only exits the block, giving it a value.
+            AbstractExpression::BlockExpression(block) => match self.block(block, &Environment::child(env)) {
+                Err(Unwind::Return(value)) => Ok(value),
+                result => result,
+            },
+            AbstractExpression::TrailingBlock(block) => self.block(block, &Environment::child(env)),
+            AbstractExpression::PropertyAccess(access) => self.property_access(access, env),
+            AbstractExpression::Unary(unary) => self.unary(unary, env),
+            AbstractExpression::Call(call) => self.call_expression(call, env),
+            AbstractExpression::If(if_) => self.if_else(if_, env),
+            AbstractExpression::List(list) => self.list(list, env),
+            AbstractExpression::Assign(assign) => self.assign(assign, env),
+            AbstractExpression::Format(format) => self.format(format, env),
+            AbstractExpression::Path(path) => {
+                let span = path.span();
+                let value = self.graph.path(path).and_then(|qualified| self.global(qualified));
+                Ok(value.ok_or_else(|| RuntimeError { span, details: format!("Undefined name `{}`.", self.sources.span_str(span)) })?)
+            }
+        }
+    }
+
+    fn binary_expression(&mut self, binary: &Binary, env: &Env) -> std::result::Result<Value, Unwind> {
+        let lhs = self.expression(&binary.lhs, env)?;
+        if matches!(binary.operator.kind, TokenKind::AndAnd | TokenKind::OrOr) {
+            // The right hand side is only evaluated if the left doesn't decide the result.
+            let holds = lhs.is_truthy();
+            return if holds == (binary.operator.kind == TokenKind::OrOr) { Ok(lhs) } else { self.expression(&binary.rhs, env) };
+        }
+        let rhs = self.expression(&binary.rhs, env)?;
+        Ok(self.binary(binary.operator, lhs, rhs)?)
+    }
+
+    fn property_access(&mut self, access: &PropertyAccess, env: &Env) -> std::result::Result<Value, Unwind> {
+        match &access.obj {
+            Some(obj) => {
+                let obj = self.expression(obj, env)?;
+                Err(Unwind::Error(RuntimeError {
+                    span: access.property.span,
+                    details: format!("Value of type {} has no property `{}`.", obj.type_name(), self.name(access.property)),
+                }))
+            }
+            None => {
+                let name = self.name(access.property);
+                Ok(env.borrow().lookup(name, self.global_name(name)).ok_or_else(|| RuntimeError {
+                    span: access.property.span,
+                    details: format!("Undefined name `{}`.", name),
+                })?)
+            }
+        }
+    }
+
+    fn unary(&mut self, unary: &Unary, env: &Env) -> std::result::Result<Value, Unwind> {
+        let value = self.expression(&unary.expr, env)?;
+        match unary.op.kind {
+            TokenKind::Bang => Ok(Value::Bool(!value.is_truthy())),
+            _ => Ok(value::negate(value).map_err(|details| RuntimeError { span: unary.op.span, details })?),
+        }
+    }
+
+    fn call_expression(&mut self, call: &Call, env: &Env) -> std::result::Result<Value, Unwind> {
+        let callee = self.expression(&call.expr, env)?;
+        let mut args = Vec::with_capacity(call.args.len());
+        for arg in &call.args {
+            args.push(self.expression(arg, env)?);
+        }
+        Ok(self.invoke(callee, args, call.expr.span().unwrap_or_default())?)
+    }
+
+    fn list(&mut self, list: &List, env: &Env) -> std::result::Result<Value, Unwind> {
+        let mut items = Vec::with_capacity(list.items.len());
+        for item in &list.items {
+            items.push(self.expression(item, env)?);
+        }
+        Ok(Value::List(Rc::new(items)))
+    }
+
+    fn format(&mut self, format: &Format, env: &Env) -> std::result::Result<Value, Unwind> {
+        let mut string = String::new();
+        for part in &format.parts {
+            match part {
+                FormatPart::Text(text) => string.push_str(text),
+                FormatPart::Expr(expr) => string.push_str(&self.expression(expr, env)?.to_string()),
+            }
+        }
+        Ok(Value::String(Rc::from(string)))
+    }
+
+    fn assign(&mut self, assign: &Assign, env: &Env) -> std::result::Result<Value, Unwind> {
+        let target = &assign.target;
+        let operator = match assign.operator.kind {
+            TokenKind::PlusEq => Some(TokenKind::Plus),
+            TokenKind::MinusEq => Some(TokenKind::Minus),
+            TokenKind::StarEq => Some(TokenKind::Star),
+            TokenKind::SlashEq => Some(TokenKind::Slash),
+            _ => None,
+        };
+        if let Some(obj) = &target.obj {
+            let obj = self.expression(obj, env)?;
+            return Err(Unwind::Error(RuntimeError {
+                span: target.property.span,
+                details: format!("Value of type {} has no property `{}`.", obj.type_name(), self.name(target.property)),
+            }));
+        }
+
+        let name = self.name(target.property);
+        let value = match operator {
+            Some(kind) => {
+                let current = env.borrow().lookup(name, self.global_name(name)).ok_or_else(|| RuntimeError {
+                    span: target.property.span,
+                    details: format!("Undefined name `{}`.", name),
+                })?;
+                let rhs = self.expression(&assign.value, env)?;
+                self.binary(Token { span: assign.operator.span, kind }, current, rhs)?
+            }
+            None => self.expression(&assign.value, env)?,
+        };
+        env.borrow_mut()
+            .assign(name, self.global_name(name), value.clone())
+            .map_err(|details| RuntimeError { span: target.property.span, details })?;
+        Ok(value)
+    }
+
+    fn if_else(&mut self, if_: &If, env: &Env) -> std::result::Result<Value, Unwind> {
+        let condition = self.expression(&if_.condition, env)?;
+        let holds = condition.is_truthy();
+        if holds {
+            self.block(&if_.then, &Environment::child(env))
+        } else {
+            match &if_.otherwise {
+                Some(Else::If(nested)) => self.if_else(nested, env),
+                Some(Else::Block(block)) => self.block(block, &Environment::child(env)),
+                None => Ok(Value::Unit),
+            }
+        }
+    }
+
+    fn invoke(&mut self, callee: Value, args: Vec<Value>, span: Span) -> Result<Value> {
+        match callee {
+            Value::Function(closure) => {
+                if closure.params.len() != args.len() {
+                    return Err(RuntimeError {
+                        span,
+                        details: format!("Function `{}` takes {} arguments but {} were given.", closure.name, closure.params.len(), args.len()),
+                    });
+                }
+                if self.depth >= MAX_DEPTH {
+                    return Err(RuntimeError { span, details: String::from("Stack overflow.") });
+                }
+                let env = Environment::child(&closure.env);
+                for (param, arg) in closure.params.iter().zip(args) {
+                    env.borrow_mut().define(param.clone(), arg, false);
+                }
+                self.depth += 1;
+                let caller = std::mem::replace(&mut self.module, closure.module);
+                let result = self.block(&closure.body, &env);
+                self.module = caller;
+                self.depth -= 1;
+                match result {
+                    Ok(value) => Ok(value),
+                    Err(Unwind::Return(value)) => Ok(value),
+                    Err(Unwind::Error(error)) => Err(error),
+                    Err(Unwind::Break(_) | Unwind::Continue(_)) => unreachable!("loop control outside of a loop"),
+                }
+            }
+            Value::Native(native) => native.call(&args, &mut self.output).map_err(|details| RuntimeError { span, details }),
+            callee => Err(RuntimeError { span, details: format!("Cannot call a value of type {}.", callee.type_name()) }),
+        }
+    }
+
+    fn binary(&self, operator: Token, lhs: Value, rhs: Value) -> Result<Value> {
+        let op = match operator.kind {
+            TokenKind::Plus => BinaryOp::Add,
+            TokenKind::Minus => BinaryOp::Sub,
+            TokenKind::Star => BinaryOp::Mul,
+            TokenKind::Slash => BinaryOp::Div,
+            TokenKind::Percent => BinaryOp::Mod,
+            TokenKind::EqEq => BinaryOp::Equal,
+            TokenKind::BangEq => BinaryOp::NotEqual,
+            TokenKind::Lt => BinaryOp::Less,
+            TokenKind::LtEq => BinaryOp::LessEqual,
+            TokenKind::Gt => BinaryOp::Greater,
+            TokenKind::GtEq => BinaryOp::GreaterEqual,
+            TokenKind::DotDot => BinaryOp::Range,
+            _ => return Err(RuntimeError { span: operator.span, details: String::from("Unsupported binary operator.") }),
+        };
+        value::binary(op, lhs, rhs).map_err(|details| RuntimeError { span: operator.span, details })
+    }
+
+    fn name(&self, token: Token) -> &'src str {
+        self.sources.span_str(token.span)
+    }
+
+    // The qualified name of the global `name` refers to in the current module. Names the module
+    // doesn't declare or import, like natives, are looked up as they are.
+    fn global_name<'a>(&self, name: &'a str) -> &'a str
+    where
+        'src: 'a,
+    {
+        self.graph.global(self.module, name).unwrap_or(name)
+    }
+
+    // The name a declaration in `env` is defined under, which is qualified at the top level.
+    fn definition(&self, ident: Token, env: &Env) -> String {
+        let name = self.name(ident);
+        match Rc::ptr_eq(env, &self.globals) {
+            true => self.graph.module(self.module).qualify(name),
+            false => String::from(name),
+        }
+    }
+
+    // The name of a label token, without its leading quote.
+    fn label(&self, token: Token) -> &'src str {
+        &self.name(token)[1..]
+    }
+}
+
+/// Runs every module in `graph` and then the root module's `main` function, if it defines one.
+pub fn run(graph: &ModuleGraph, sources: &SourceMap) -> Result<Value> {
+    Interpreter::new(graph, sources).run()
+}
@@ -5,11 +5,26 @@ use crate::span::*;
 use std::iter::Peekable;
 use std::str::Chars;
 
-const KEYWORDS: [(&'static str, TokenKind); 4] = [
+const KEYWORDS: [(&str, TokenKind); 19] = [
     ("if", TokenKind::If),
+    ("else", TokenKind::Else),
     ("for", TokenKind::For),
+    ("in", TokenKind::In),
+    ("while", TokenKind::While),
+    ("break", TokenKind::Break),
+    ("continue", TokenKind::Continue),
     ("fun", TokenKind::Fun),
+    ("let", TokenKind::Let),
+    ("mut", TokenKind::Mut),
+    ("true", TokenKind::True),
+    ("false", TokenKind::False),
     ("return", TokenKind::Return),
+    ("use", TokenKind::Use),
+    ("mod", TokenKind::Mod),
+    ("pub", TokenKind::Pub),
+    ("as", TokenKind::As),
+    ("super", TokenKind::Super),
+    ("crate", TokenKind::Crate),
 ];
 
 #[derive(Debug)]
@@ -25,28 +40,40 @@ pub struct Lexer<'a> {
 }
 
 impl<'a> Lexer<'a> {
-    pub(super) fn new(input: &'a str) -> Lexer<'a> {
+    pub(super) fn new(input: &'a str, file: FileId) -> Lexer<'a> {
+        Lexer::at(input, Span::new(file, 0, input.len()))
+    }
+
+    // Lexes the part of `input` covered by `span`, with spans still pointing into the whole input.
+    pub(super) fn at(input: &'a str, span: Span) -> Lexer<'a> {
         Lexer {
             src: input,
-            iter: input.chars().peekable(),
-            span: Default::default(),
+            iter: input[span.range()].chars().peekable(),
+            span: Span { end: span.start, ..span },
         }
     }
 
     fn bump(&mut self) -> Option<char> {
         let c = self.iter.next();
-        c.map(|c| {
-            self.span.notice(c);
-            c
-        })
+        c.inspect(|&c| self.span.notice(c))
     }
 
     fn peek(&mut self) -> Option<char> {
         self.iter.peek().copied()
     }
 
+    // The character `n` places after the next one, without consuming anything.
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.iter.clone().nth(n)
+    }
+
     fn span_str(&self) -> &'a str {
-        &self.src[self.span.0.index..self.span.1.index]
+        &self.src[self.span.range()]
+    }
+
+    // The span from `start` to the current position.
+    fn since(&self, start: u32) -> Span {
+        Span { start, ..self.span }
     }
 
     fn bump_while(&mut self, mut pred: impl FnMut(char) -> bool) {
@@ -57,57 +84,282 @@ impl<'a> Lexer<'a> {
     }
 
     pub fn token(&mut self) -> Option<Result<Token, LexError>> {
-        self.reset_span();
-        let c = self.bump()?;
-        if is_whitespace(c) {
-            return self.token();
+        if let Err(error) = self.skip_trivia() {
+            return Some(Err(error));
         }
+        let c = self.bump()?;
 
         match match c {
-            '*' => Ok(TokenKind::Star),
-            '/' => Ok(TokenKind::Slash),
-            '+' => Ok(TokenKind::Plus),
-            '-' => Ok(TokenKind::Minus),
-            '=' => {
-                Ok(if let Some('=') = self.peek() {
+            '*' => Ok(self.compound(TokenKind::Star, TokenKind::StarEq)),
+            '/' if self.peek() == Some('/') => {
+                self.bump_while(|c| c != '\n');
+                Ok(TokenKind::DocComment)
+            }
+            '/' => Ok(self.compound(TokenKind::Slash, TokenKind::SlashEq)),
+            '+' => Ok(self.compound(TokenKind::Plus, TokenKind::PlusEq)),
+            '-' if self.peek() == Some('>') => {
+                self.bump();
+                Ok(TokenKind::Arrow)
+            }
+            '-' => Ok(self.compound(TokenKind::Minus, TokenKind::MinusEq)),
+            '%' => Ok(TokenKind::Percent),
+            '=' => Ok(self.compound(TokenKind::Eq, TokenKind::EqEq)),
+            '!' => Ok(self.compound(TokenKind::Bang, TokenKind::BangEq)),
+            '<' => Ok(self.compound(TokenKind::Lt, TokenKind::LtEq)),
+            '>' => Ok(self.compound(TokenKind::Gt, TokenKind::GtEq)),
+            '&' => self.doubled('&', TokenKind::AndAnd),
+            '|' => self.doubled('|', TokenKind::OrOr),
+            '.' => {
+                Ok(if let Some('.') = self.peek() {
                     self.bump();
-                    TokenKind::Eq
+                    TokenKind::DotDot
                 } else {
-                    TokenKind::EqEq
+                    TokenKind::Dot
                 })
             }
-            '!' => {
-                Ok(if let Some('=') = self.peek() {
+            ',' => Ok(TokenKind::Comma),
+            ';' => Ok(TokenKind::Semi),
+            ':' => {
+                Ok(if let Some(':') = self.peek() {
                     self.bump();
-                    TokenKind::BangEq
+                    TokenKind::ColonColon
                 } else {
-                    TokenKind::Bang
+                    TokenKind::Colon
                 })
             }
-            '.' => Ok(TokenKind::Dot),
-            ',' => Ok(TokenKind::Comma),
-            ';' => Ok(TokenKind::Semi),
             '(' => Ok(TokenKind::LParen),
             ')' => Ok(TokenKind::RParen),
             '{' => Ok(TokenKind::LBrace),
             '}' => Ok(TokenKind::RBrace),
-            '\"' => self.string(),
-            _ if c.is_digit(10) => Ok(self.number()),
+            '[' => Ok(TokenKind::LBracket),
+            ']' => Ok(TokenKind::RBracket),
+            '\'' => self.label(),
+            '\"' => self.string_body(false).map(|_| TokenKind::String),
+            'r' if self.peek() == Some('\"') => {
+                self.bump();
+                self.string_body(true).map(|_| TokenKind::String)
+            }
+            _ if c.is_ascii_digit() => self.number(c),
             _ if is_symbol_start(c) => Ok(self.ident_or_kw()),
-            _ => return None,
+            _ => Err(self.error(format!("Unexpected character '{}'.", c.escape_debug()))),
         } {
             Ok(kind) => Some(Ok(Token { span: self.span, kind })),
             Err(error) => Some(Err(error)),
         }
     }
 
-    fn string(&mut self) -> Result<TokenKind, LexError> {
-        self.bump_while(|c| c != '\"');
-        match self.bump() {
-            Some('\"') => (),
-            _ => return Err(self.error(String::from("Expected quote after string."))), 
+    // Skips whitespace and comments, other than doc comments, leaving the span empty at the start
+    // of the next token.
+    fn skip_trivia(&mut self) -> Result<(), LexError> {
+        loop {
+            self.reset_span();
+            match (self.peek(), self.peek_nth(1)) {
+                (Some(c), _) if is_whitespace(c) => {
+                    self.bump();
+                }
+                // `///` starts a doc comment, but `////` is an ordinary comment.
+                (Some('/'), Some('/')) if self.peek_nth(2) != Some('/') || self.peek_nth(3) == Some('/') => {
+                    self.bump_while(|c| c != '\n');
+                }
+                (Some('/'), Some('*')) => {
+                    self.bump();
+                    self.bump();
+                    self.block_comment()?;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // Skips the rest of a block comment after its opening `/*`. Block comments nest.
+    fn block_comment(&mut self) -> Result<(), LexError> {
+        let open = self.span;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.bump() {
+                Some('/') if self.peek() == Some('*') => {
+                    self.bump();
+                    depth += 1;
+                }
+                Some('*') if self.peek() == Some('/') => {
+                    self.bump();
+                    depth -= 1;
+                }
+                Some(_) => (),
+                None => return Err(LexError { span: open, details: String::from("Unterminated block comment.") }),
+            }
+        }
+        Ok(())
+    }
+
+    // Lexes `with_eq` if the next character is '=', or `alone` otherwise.
+    fn compound(&mut self, alone: TokenKind, with_eq: TokenKind) -> TokenKind {
+        if let Some('=') = self.peek() {
+            self.bump();
+            with_eq
+        } else {
+            alone
+        }
+    }
+
+    // Lexes `kind` if `c` is repeated, since `c` alone isn't an operator.
+    fn doubled(&mut self, c: char, kind: TokenKind) -> Result<TokenKind, LexError> {
+        if self.peek() == Some(c) {
+            self.bump();
+            Ok(kind)
+        } else {
+            Err(self.error(format!("Expected '{}' after '{}'.", c, c)))
+        }
+    }
+
+    // Lexes the rest of a string literal after its opening quote. Raw strings have no escapes or
+    // interpolation.
+    fn string_body(&mut self, raw: bool) -> Result<Vec<Segment>, LexError> {
+        let mut segments = vec![];
+        let mut text = String::new();
+        // Keep going after a bad escape so the rest of the string isn't lexed as code.
+        let mut error = None;
+        loop {
+            let start = self.span.end;
+            let Some(c) = self.bump() else {
+                return Err(self.error(String::from("Expected quote after string.")));
+            };
+            match c {
+                '\"' => break,
+                '\\' if !raw => match self.escape(start) {
+                    Ok(c) => text.push(c),
+                    Err(escape) => {
+                        error.get_or_insert(escape);
+                    }
+                },
+                '{' | '}' if !raw && self.peek() == Some(c) => {
+                    self.bump();
+                    text.push(c);
+                }
+                // `{0}` is a placeholder for `printf` to fill in, so it's kept as text.
+                '{' if !raw && self.placeholder() => {
+                    let digits = self.span.end;
+                    self.bump_while(|c| c != '}');
+                    self.bump();
+                    text.push('{');
+                    text.push_str(&self.src[digits as usize..self.span.end as usize]);
+                }
+                '{' if !raw => match self.interpolation(start) {
+                    Ok(span) => {
+                        if !text.is_empty() {
+                            segments.push(Segment::Text(std::mem::take(&mut text)));
+                        }
+                        segments.push(Segment::Interpolation(span));
+                    }
+                    Err(interpolation) if self.peek().is_none() => return Err(interpolation),
+                    Err(interpolation) => {
+                        error.get_or_insert(interpolation);
+                    }
+                },
+                '}' if !raw => {
+                    let span = self.since(start);
+                    error.get_or_insert(LexError { span, details: String::from("Unmatched '}' in string. Use '}}' for a literal brace.") });
+                }
+                c => text.push(c),
+            }
+        }
+        if let Some(error) = error {
+            return Err(error);
+        }
+        if !text.is_empty() || segments.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(segments)
+    }
+
+    // Whether the `{` just lexed starts a `{N}` placeholder, which is only digits.
+    fn placeholder(&self) -> bool {
+        let digits = self.iter.clone().take_while(char::is_ascii_digit).count();
+        digits > 0 && self.peek_nth(digits) == Some('}')
+    }
+
+    // Lexes the escape sequence after a backslash at `start`.
+    fn escape(&mut self, start: u32) -> Result<char, LexError> {
+        let c = match self.bump() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('\"') => '\"',
+            Some('\'') => '\'',
+            Some('u') => return self.unicode_escape(start),
+            Some(c) => {
+                let span = self.since(start);
+                return Err(LexError { span, details: format!("Unknown escape sequence '\\{}'.", c) });
+            }
+            None => return Err(self.error(String::from("Expected quote after string."))),
         };
-        Ok(TokenKind::String)
+        Ok(c)
+    }
+
+    fn unicode_escape(&mut self, start: u32) -> Result<char, LexError> {
+        let error = |lexer: &Lexer, details: &str| LexError { span: lexer.since(start), details: String::from(details) };
+        if self.peek() != Some('{') {
+            return Err(error(self, "Expected '{' after '\\u'."));
+        }
+        self.bump();
+        let digits = self.span.end as usize;
+        self.bump_while(|c| c.is_ascii_hexdigit());
+        let digits = &self.src[digits..self.span.end as usize];
+        if self.peek() != Some('}') {
+            return Err(error(self, "Expected '}' to close unicode escape."));
+        }
+        self.bump();
+        if digits.is_empty() || digits.len() > 6 {
+            return Err(error(self, "A unicode escape must have between 1 and 6 hex digits."));
+        }
+        u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| error(self, "Invalid unicode character in escape."))
+    }
+
+    // Lexes the expression inside `{...}` in a string, where `open` is the position of the brace,
+    // and returns the span of the expression.
+    fn interpolation(&mut self, open: u32) -> Result<Span, LexError> {
+        let outer = self.span.start;
+        let start = self.span.end;
+        let mut depth = 0;
+        let mut empty = true;
+        let close = loop {
+            let tok = match self.token() {
+                Some(Ok(tok)) => tok,
+                Some(Err(error)) if self.peek().is_some() => return Err(error),
+                // Running out of input, even inside a nested string, means the brace was never closed.
+                _ => {
+                    self.span.start = open;
+                    return Err(self.error(String::from("Expected '}' to close interpolation in string.")));
+                }
+            };
+            match tok.kind {
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace if depth == 0 => break self.span.start,
+                TokenKind::RBrace => depth -= 1,
+                _ => (),
+            }
+            empty = false;
+        };
+        self.span.start = outer;
+        if empty {
+            return Err(LexError { span: self.since(open), details: String::from("Expected an expression inside '{}' in string.") });
+        }
+        Ok(Span { start, end: close, ..self.span })
+    }
+
+    fn label(&mut self) -> Result<TokenKind, LexError> {
+        match self.peek() {
+            Some(c) if is_symbol_start(c) => {
+                self.bump_while(is_symbol_continue);
+                Ok(TokenKind::Label)
+            }
+            _ => Err(self.error(String::from("Expected a label name after '\''."))),
+        }
     }
 
     fn ident_or_kw(&mut self) -> TokenKind {
@@ -124,14 +376,58 @@ impl<'a> Lexer<'a> {
         TokenKind::Ident
     }
 
-    fn number(&mut self) -> TokenKind {
-        // TODO: Add support for signed ints and floats.
-        self.bump_while(|c| c.is_digit(10));
-        TokenKind::UInt
+    fn number(&mut self, first: char) -> Result<TokenKind, LexError> {
+        let radix = match (first, self.peek()) {
+            ('0', Some('x')) => 16,
+            ('0', Some('o')) => 8,
+            ('0', Some('b')) => 2,
+            _ => 10,
+        };
+        let is_digit = |c: char| c.is_digit(radix) || c == '_';
+        let mut float = false;
+        if radix == 10 {
+            self.bump_while(is_digit);
+            // `0..2` is a range, so a dot only starts a fraction when a digit follows it.
+            if self.peek() == Some('.') && self.peek_nth(1).is_some_and(|c| c.is_ascii_digit()) {
+                self.bump();
+                self.bump_while(is_digit);
+                float = true;
+            }
+            let signed = matches!(self.peek_nth(1), Some('+' | '-'));
+            let exponent = self.peek_nth(if signed { 2 } else { 1 }).is_some_and(|c| c.is_ascii_digit());
+            if matches!(self.peek(), Some('e' | 'E')) && exponent {
+                self.bump();
+                if signed {
+                    self.bump();
+                }
+                self.bump_while(is_digit);
+                float = true;
+            }
+        } else {
+            let prefix = self.bump().unwrap_or_default();
+            let start = self.span.end as usize;
+            self.bump_while(is_digit);
+            if self.src[start..self.span.end as usize].chars().all(|c| c == '_') {
+                return Err(self.error(format!("Expected digits after '0{}'.", prefix)));
+            }
+        }
+
+        let start = self.span.end as usize;
+        self.bump_while(is_symbol_continue);
+        let kind = match &self.src[start..self.span.end as usize] {
+            "" if float => TokenKind::Float,
+            "" | "u" if !float => TokenKind::UInt,
+            "i" if !float => TokenKind::Int,
+            "f" if radix == 10 => TokenKind::Float,
+            suffix => return Err(self.error(format!("Invalid suffix `{}` on number literal.", suffix))),
+        };
+        // Check the value now so an out of range literal is reported before parsing.
+        number_value(self.span_str()).map_err(|details| self.error(details))?;
+        Ok(kind)
     }
 
     fn error(&mut self, details: String) -> LexError {
-        LexError { span: self.span, details: details }
+        LexError { span: self.span, details }
     }
 
     pub fn span(&self) -> Span {
@@ -143,6 +439,65 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// A piece of a string literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Text(String),
+    // The span of an interpolated expression, without its braces.
+    Interpolation(Span),
+}
+
+/// Splits the string literal at `span` into text, with escapes resolved, and interpolations.
+pub fn string_segments(src: &str, span: Span) -> Result<Vec<Segment>, LexError> {
+    let mut lexer = Lexer::at(src, span);
+    let raw = lexer.bump() == Some('r');
+    if raw {
+        lexer.bump();
+    }
+    lexer.string_body(raw)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Number {
+    UInt(u64),
+    Int(i64),
+    Float(f64),
+}
+
+/// The value of a number literal, which may have a `0x`, `0o` or `0b` prefix, `_` separators and a
+/// `u`, `i` or `f` suffix. Integers without a suffix are unsigned.
+pub fn number_value(text: &str) -> Result<Number, String> {
+    let text = text.replace('_', "");
+    let (radix, digits) = match text.get(..2) {
+        Some("0x") => (16, &text[2..]),
+        Some("0o") => (8, &text[2..]),
+        Some("0b") => (2, &text[2..]),
+        _ => (10, &text[..]),
+    };
+    // `f` is a digit in hexadecimal, so it's only a suffix on decimal literals.
+    let suffix = digits.chars().last().filter(|&c| matches!(c, 'u' | 'i') || (radix == 10 && c == 'f'));
+    let digits = if suffix.is_some() { &digits[..digits.len() - 1] } else { digits };
+
+    let float = suffix == Some('f') || (radix == 10 && digits.contains(['.', 'e', 'E']));
+    if float {
+        let value: f64 = digits.parse().map_err(|_| format!("Invalid float literal `{}`.", text))?;
+        return if value.is_finite() {
+            Ok(Number::Float(value))
+        } else {
+            Err(String::from("Float literal is out of range."))
+        };
+    }
+    let invalid = |error: std::num::ParseIntError, kind: &str| match error.kind() {
+        std::num::IntErrorKind::PosOverflow => format!("Integer literal is too large for type {}.", kind),
+        _ => format!("Invalid integer literal `{}`.", text),
+    };
+    if suffix == Some('i') {
+        i64::from_str_radix(digits, radix).map(Number::Int).map_err(|error| invalid(error, "int"))
+    } else {
+        u64::from_str_radix(digits, radix).map(Number::UInt).map_err(|error| invalid(error, "uint"))
+    }
+}
+
 fn is_symbol_start(c: char) -> bool {
     UnicodeXID::is_xid_start(c)
 }
@@ -151,6 +506,7 @@ fn is_symbol_continue(c: char) -> bool {
     UnicodeXID::is_xid_continue(c)
 }
 
+// Any Unicode whitespace, which includes the '\r' of Windows line endings.
 fn is_whitespace(c: char) -> bool {
-    matches!(c, ' ' | '\t' | '\n')
+    c.is_whitespace()
 }
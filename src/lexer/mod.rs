@@ -1,6 +1,6 @@
 use std::{collections::VecDeque, fmt::Debug};
 
-use crate::{span::Span, iter::TakeErrorsExt};
+use crate::{span::{FileId, Span}, iter::TakeErrorsExt};
 
 use self::{lex::{Lexer, LexError}, token::Token};
 
@@ -9,23 +9,37 @@ pub mod token;
 
 pub struct TokenStream {
     queue: VecDeque<Token>,
-    span: Span,
+    last: Option<Token>,
 }
 
 impl TokenStream {
     pub fn new(mut lexer: Lexer) -> Result<TokenStream, Vec<LexError>> {
         Ok(TokenStream {
             queue: std::iter::from_fn(move || lexer.token()).take_errors()?.collect(),
-            span: Default::default(),
+            last: None,
         })
     }
 
     pub fn peek(&mut self) -> Option<Token> {
-        self.queue.get(0).copied()
+        self.queue.front().copied()
     }
 
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// The last token taken from the stream.
+    pub fn previous(&self) -> Option<Token> {
+        self.last
+    }
+
+    /// The span of the last token taken from the stream.
     pub fn span(&self) -> Span {
-        self.span
+        self.last.map(|tok| tok.span).unwrap_or_default()
     }
 }
 
@@ -33,7 +47,9 @@ impl Iterator for TokenStream {
     type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.queue.pop_front()
+        let tok = self.queue.pop_front()?;
+        self.last = Some(tok);
+        Some(tok)
     }
 }
 
@@ -46,6 +62,11 @@ impl Debug for TokenStream {
     }
 }
 
-pub fn tokenize(input: &str) -> Result<TokenStream, Vec<LexError>> {
-    TokenStream::new(Lexer::new(input))
+pub fn tokenize(input: &str, file: FileId) -> Result<TokenStream, Vec<LexError>> {
+    TokenStream::new(Lexer::new(input, file))
+}
+
+/// Tokenizes only the part of `input` covered by `span`, such as an interpolation in a string.
+pub fn tokenize_span(input: &str, span: Span) -> Result<TokenStream, Vec<LexError>> {
+    TokenStream::new(Lexer::at(input, span))
 }
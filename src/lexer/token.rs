@@ -1,5 +1,5 @@
 use crate::span::Span;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 
 #[derive(Copy, Debug, Clone, PartialEq, Eq)]
 pub enum TokenKind {
@@ -7,29 +7,63 @@ pub enum TokenKind {
     Minus,
     Star,
     Slash,
+    Percent,
+    PlusEq,
+    MinusEq,
+    StarEq,
+    SlashEq,
 
     True,
     False,
     UInt,
+    Int,
+    Float,
     String,
     Ident,
+    DocComment,
 
     If,
+    Else,
     For,
+    In,
+    While,
+    Break,
+    Continue,
     Return,
     Fun,
+    Let,
+    Mut,
+    Use,
+    Mod,
+    Pub,
+    As,
+    Super,
+    Crate,
+    Label,
 
     LParen,
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
 
     Eq,
     EqEq,
     Bang,
     BangEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    AndAnd,
+    OrOr,
     Dot,
+    DotDot,
     Semi,
+    Colon,
+    ColonColon,
+    Arrow,
     Comma,
 }
 
@@ -39,40 +73,86 @@ pub struct Token {
     pub kind: TokenKind,
 }
 
+impl Token {
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
 impl Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Token[{}]", self.kind)
+    }
+}
+
+impl Display for TokenKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use self::TokenKind::*;
         write!(
             f,
-            "Token[{}]",
-            match &self.kind {
+            "{}",
+            match self {
                 Plus => "+",
                 Minus => "-",
                 Star => "*",
                 Slash => "/",
+                Percent => "%",
+                PlusEq => "+=",
+                MinusEq => "-=",
+                StarEq => "*=",
+                SlashEq => "/=",
 
                 True => "true",
                 False => "false",
                 UInt => "<uint>",
+                Int => "<int>",
+                Float => "<float>",
                 String => "<string>",
                 Ident => "<ident>",
+                DocComment => "<doc comment>",
 
                 If => "if",
+                Else => "else",
                 For => "for",
+                In => "in",
+                While => "while",
+                Break => "break",
+                Continue => "continue",
                 Return => "return",
                 Fun => "fun",
+                Let => "let",
+                Mut => "mut",
+                Use => "use",
+                Mod => "mod",
+                Pub => "pub",
+                As => "as",
+                Super => "super",
+                Crate => "crate",
+                Label => "<label>",
 
                 LParen => "(",
                 RParen => ")",
                 LBrace => "{",
                 RBrace => "}",
+                LBracket => "[",
+                RBracket => "]",
 
                 Eq => "=",
                 EqEq => "==",
                 Bang => "!",
                 BangEq => "!=",
+                Lt => "<",
+                LtEq => "<=",
+                Gt => ">",
+                GtEq => ">=",
+                AndAnd => "&&",
+                OrOr => "||",
                 Dot => ".",
+                DotDot => "..",
                 Semi => ";",
+                Colon => ":",
+                ColonColon => "::",
+                Arrow => "->",
                 Comma => ",",
             }
         )
@@ -85,18 +165,46 @@ macro_rules! Tok {
     [-] => { $crate::lexer::token::TokenKind::Minus };
     [*] => { $crate::lexer::token::TokenKind::Star };
     [/] => { $crate::lexer::token::TokenKind::Slash };
+    [%] => { $crate::lexer::token::TokenKind::Percent };
+    [+=] => { $crate::lexer::token::TokenKind::PlusEq };
+    [-=] => { $crate::lexer::token::TokenKind::MinusEq };
+    [*=] => { $crate::lexer::token::TokenKind::StarEq };
+    [/=] => { $crate::lexer::token::TokenKind::SlashEq };
     [true] => { $crate::lexer::token::TokenKind::True };
     [false] => { $crate::lexer::token::TokenKind::False };
     [if] => { $crate::lexer::token::TokenKind::If };
+    [else] => { $crate::lexer::token::TokenKind::Else };
     [for] => { $crate::lexer::token::TokenKind::For };
+    [in] => { $crate::lexer::token::TokenKind::In };
+    [while] => { $crate::lexer::token::TokenKind::While };
+    [break] => { $crate::lexer::token::TokenKind::Break };
+    [continue] => { $crate::lexer::token::TokenKind::Continue };
     [return] => { $crate::lexer::token::TokenKind::Return };
-    [function] => { $crate::lexer::token::TokenKind::Function };
-    [=] => { $crate::lexer::token::TokenKind::Eq }; 
+    [let] => { $crate::lexer::token::TokenKind::Let };
+    [mut] => { $crate::lexer::token::TokenKind::Mut };
+    [fun] => { $crate::lexer::token::TokenKind::Fun };
+    [use] => { $crate::lexer::token::TokenKind::Use };
+    [mod] => { $crate::lexer::token::TokenKind::Mod };
+    [pub] => { $crate::lexer::token::TokenKind::Pub };
+    [as] => { $crate::lexer::token::TokenKind::As };
+    [super] => { $crate::lexer::token::TokenKind::Super };
+    [crate] => { $crate::lexer::token::TokenKind::Crate };
+    [=] => { $crate::lexer::token::TokenKind::Eq };
     [==] => { $crate::lexer::token::TokenKind::EqEq };
     [!] => { $crate::lexer::token::TokenKind::Bang };
     [!=] => { $crate::lexer::token::TokenKind::BangEq };
+    [<] => { $crate::lexer::token::TokenKind::Lt };
+    [<=] => { $crate::lexer::token::TokenKind::LtEq };
+    [>] => { $crate::lexer::token::TokenKind::Gt };
+    [>=] => { $crate::lexer::token::TokenKind::GtEq };
+    [&&] => { $crate::lexer::token::TokenKind::AndAnd };
+    [||] => { $crate::lexer::token::TokenKind::OrOr };
     [.] => { $crate::lexer::token::TokenKind::Dot };
+    [..] => { $crate::lexer::token::TokenKind::DotDot };
     [;] => { $crate::lexer::token::TokenKind::Semi };
+    [:] => { $crate::lexer::token::TokenKind::Colon };
+    [::] => { $crate::lexer::token::TokenKind::ColonColon };
+    [->] => { $crate::lexer::token::TokenKind::Arrow };
     [,] => { $crate::lexer::token::TokenKind::Comma };
 }
 
@@ -1,8 +1,33 @@
 pub mod lexer;
 pub mod parser;
 pub mod span;
+pub mod source_map;
 pub mod iter;
 pub mod bytecode;
+pub mod compiler;
+pub mod value;
+pub mod vm;
+pub mod interp;
+pub mod diagnostics;
+pub mod modules;
+pub mod types;
 
-#[cfg(test)]
-mod tests {}
+use diagnostics::Diagnostic;
+use parser::{ast::Ast, ParseStream};
+use source_map::SourceMap;
+use span::FileId;
+
+/// Tokenizes and parses a whole source file, returning every error as a diagnostic.
+pub fn parse_file(sources: &SourceMap, file: FileId) -> Result<Ast, Vec<Diagnostic>> {
+    let src = sources.src(file);
+    let tokens = lexer::tokenize(src, file).map_err(|errors| errors.iter().map(Diagnostic::from).collect::<Vec<_>>())?;
+    let mut stream = ParseStream::new(tokens, src);
+    parser::program(&mut stream).map_err(|errors| errors.iter().map(Diagnostic::from).collect())
+}
+
+/// Parses `src` as a file of its own, returning the rendered errors if it doesn't parse.
+pub fn parse_source(src: &str) -> Result<Ast, String> {
+    let mut sources = SourceMap::new();
+    let file = sources.add("<source>", src);
+    parse_file(&sources, file).map_err(|diagnostics| diagnostics.iter().map(|diagnostic| diagnostic.render(&sources, false)).collect())
+}
@@ -1,20 +1,43 @@
 extern crate circuit_lang as circuit;
 
-use circuit::{lexer, parser::ParseStream};
-use circuit::parser;
+use circuit::{compiler, modules, types, vm};
+use circuit::diagnostics::Diagnostic;
+use circuit::source_map::SourceMap;
 
 const CODE: &str = r#"
 fun hello() {
     print("Cool");
     1+2;
 }
+
+fun main() {
+    hello();
+}
 "#;
 
+fn report(sources: &SourceMap, diagnostics: impl IntoIterator<Item = Diagnostic>) -> ! {
+    for diagnostic in diagnostics {
+        eprint!("{}", diagnostic.render(sources, true));
+    }
+    std::process::exit(1);
+}
+
 fn main() {
-    let tokens = lexer::tokenize(CODE).map_err(|errors| format!("Unable tokenize input: {:?}", errors.into_iter().map(|error| error.details))).unwrap();
-    println!("Tokens {:#?}", tokens);
-    //println!("{:#?}", tokens.collect::<Vec<Token>>());
-    let mut parse_stream = ParseStream::new(tokens, CODE);
-    
-    println!("AST {:#?}", parser::statement(&mut parse_stream));
+    let mut sources = SourceMap::new();
+    let file = sources.add("<main>", CODE);
+    let graph = modules::load(&mut sources, file).unwrap_or_else(|diagnostics| report(&sources, diagnostics));
+    for warning in graph.warnings() {
+        eprint!("{}", warning.render(&sources, true));
+    }
+    let errors = types::check(&graph, &sources);
+    if !errors.is_empty() {
+        report(&sources, errors.iter().map(Diagnostic::from));
+    }
+    println!("AST {:#?}", graph.module(graph.root()).ast);
+    let program = compiler::compile(&graph, &sources).unwrap_or_else(|errors| report(&sources, errors.iter().map(Diagnostic::from)));
+    println!("Program {:#?}", program);
+    match vm::run(program) {
+        Ok(value) => println!("Result {}", value),
+        Err(error) => report(&sources, [Diagnostic::from(&error)]),
+    }
 }
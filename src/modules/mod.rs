@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::path::{Path as FilePath, PathBuf};
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::parser::ast::*;
+use crate::source_map::SourceMap;
+use crate::span::{FileId, Span};
+use scope::ScopeTree;
+
+mod resolve;
+pub mod scope;
+
+/// The extension of source files, which `mod` declarations look for.
+pub const EXTENSION: &str = "cir";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModuleId(pub usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveError {
+    pub span: Span,
+    pub details: String,
+    // Other places involved in the error, like the rest of an import cycle.
+    pub related: Vec<(Span, String)>,
+}
+
+/// What a name in a module refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    // A top-level `fun` or `let`, with the module it's declared in and its qualified name.
+    Global(ModuleId, String),
+    Module(ModuleId),
+}
+
+#[derive(Debug, Clone)]
+pub struct Name {
+    pub target: Target,
+    pub public: bool,
+    // Where the name was declared or imported.
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct Module {
+    // The names leading to the module from the root, which has none.
+    pub path: Vec<String>,
+    pub file: FileId,
+    pub parent: Option<ModuleId>,
+    pub ast: Ast,
+    pub children: HashMap<String, ModuleId>,
+    // Everything declared or imported at the top level of the module.
+    pub names: HashMap<String, Name>,
+    pub scopes: ScopeTree,
+}
+
+impl Module {
+    /// The name a global declared in this module is stored under. The root module's globals keep
+    /// their own names, and everything else is prefixed with the module's path, like `a::b::name`.
+    pub fn qualify(&self, name: &str) -> String {
+        match self.path.is_empty() {
+            true => String::from(name),
+            false => format!("{}::{}", self.path.join("::"), name),
+        }
+    }
+
+    pub fn display_name(&self) -> String {
+        match self.path.is_empty() {
+            true => String::from("crate"),
+            false => self.path.join("::"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ModuleGraph {
+    modules: Vec<Module>,
+    // Modules in the order their top-level code runs.
+    order: Vec<ModuleId>,
+    // The qualified name of the global each path expression refers to, by the path's span.
+    paths: HashMap<Span, String>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl ModuleGraph {
+    pub fn root(&self) -> ModuleId {
+        ModuleId(0)
+    }
+
+    pub fn module(&self, id: ModuleId) -> &Module {
+        &self.modules[id.0]
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = (ModuleId, &Module)> {
+        self.modules.iter().enumerate().map(|(id, module)| (ModuleId(id), module))
+    }
+
+    /// Modules in the order their top-level code runs. Each module comes after the ones it uses,
+    /// unless they use each other.
+    pub fn order(&self) -> &[ModuleId] {
+        &self.order
+    }
+
+    /// The qualified name of the global that `name` refers to at the top level of `module`.
+    pub fn global(&self, module: ModuleId, name: &str) -> Option<&str> {
+        match &self.module(module).names.get(name)?.target {
+            Target::Global(_, qualified) => Some(qualified),
+            Target::Module(_) => None,
+        }
+    }
+
+    /// Warnings about the program that don't stop it from running, like shadowed names.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// The qualified name of the global a path expression refers to.
+    pub fn path(&self, path: &Path) -> Option<&str> {
+        self.paths.get(&path.span()).map(String::as_str)
+    }
+}
+
+/// Parses `root` and every module it declares, loading their files into `sources`, then resolves
+/// the imports and paths between them and every name used in them. Types aren't checked here, see
+/// `types::check`.
+pub fn load(sources: &mut SourceMap, root: FileId) -> Result<ModuleGraph, Vec<Diagnostic>> {
+    let mut loader = Loader { sources, modules: Vec::new(), files: HashMap::new(), diagnostics: Vec::new() };
+    loader.module(root, Vec::new(), None);
+    let Loader { sources, modules, diagnostics, .. } = loader;
+    // Resolving names in a file that didn't parse would only report more of the same errors.
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    let mut graph = ModuleGraph { modules, order: Vec::new(), paths: HashMap::new(), warnings: Vec::new() };
+    let errors = resolve::resolve(&mut graph, sources);
+    if !errors.is_empty() {
+        return Err(errors.iter().map(Diagnostic::from).collect());
+    }
+    let (errors, warnings) = scope::resolve(&mut graph, sources);
+    graph.warnings = warnings.iter().map(|warning| Diagnostic { severity: Severity::Warning, ..Diagnostic::from(warning) }).collect();
+    if errors.is_empty() {
+        Ok(graph)
+    } else {
+        Err(errors.iter().map(Diagnostic::from).chain(graph.warnings).collect())
+    }
+}
+
+struct Loader<'a> {
+    sources: &'a mut SourceMap,
+    modules: Vec<Module>,
+    // Every file loaded so far, so the same file can't become two modules.
+    files: HashMap<PathBuf, ModuleId>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Loader<'_> {
+    fn module(&mut self, file: FileId, path: Vec<String>, parent: Option<ModuleId>) -> ModuleId {
+        let id = ModuleId(self.modules.len());
+        let name = self.sources.file(file).name();
+        self.files.insert(canonical(name), id);
+        let ast = crate::parse_file(self.sources, file).unwrap_or_else(|diagnostics| {
+            self.diagnostics.extend(diagnostics);
+            Vec::new()
+        });
+        self.modules.push(Module { path, file, parent, ast: Vec::new(), children: HashMap::new(), names: HashMap::new(), scopes: ScopeTree::default() });
+
+        for stmt in &ast {
+            let AbstractStatement::Module(decl) = stmt else {
+                continue;
+            };
+            let name = String::from(self.sources.span_str(decl.ident.span));
+            if self.modules[id.0].children.contains_key(&name) {
+                let error = Diagnostic::error(format!("Module `{}` is declared more than once.", name), decl.ident.span);
+                self.diagnostics.push(error);
+                continue;
+            }
+            let Some(child_path) = self.find(file, parent.is_none(), &name, decl.ident.span) else {
+                continue;
+            };
+            if let Some(&other) = self.files.get(&canonical(&child_path.display().to_string())) {
+                let other = self.modules[other.0].display_name();
+                let error = Diagnostic::error(format!("The file for module `{}` is already loaded as module `{}`.", name, other), decl.ident.span);
+                self.diagnostics.push(error);
+                continue;
+            }
+            let child_file = match self.sources.load(&child_path) {
+                Ok(child_file) => child_file,
+                Err(error) => {
+                    let error = Diagnostic::error(format!("Cannot read `{}`: {}.", child_path.display(), error), decl.ident.span);
+                    self.diagnostics.push(error);
+                    continue;
+                }
+            };
+            let mut child_module_path = self.modules[id.0].path.clone();
+            child_module_path.push(name.clone());
+            let child = self.module(child_file, child_module_path, Some(id));
+            self.modules[id.0].children.insert(name, child);
+        }
+        self.modules[id.0].ast = ast;
+        id
+    }
+
+    // Finds the file of module `name`, declared in `file`. Modules declared in the root file or a
+    // `mod.cir` live next to it, and modules declared in `a.cir` live in the directory `a`.
+    fn find(&mut self, file: FileId, root: bool, name: &str, span: Span) -> Option<PathBuf> {
+        let path = FilePath::new(self.sources.file(file).name());
+        let parent = path.parent().unwrap_or(FilePath::new(""));
+        let dir = match path.file_stem() {
+            Some(stem) if !root && stem != "mod" => parent.join(stem),
+            _ => parent.to_path_buf(),
+        };
+        let candidates = [dir.join(format!("{}.{}", name, EXTENSION)), dir.join(name).join(format!("mod.{}", EXTENSION))];
+        if let Some(found) = candidates.iter().find(|candidate| candidate.is_file()) {
+            return Some(found.clone());
+        }
+        let error = Diagnostic::error(format!("Cannot find a file for module `{}`.", name), span)
+            .with_help(format!("create `{}` or `{}`", candidates[0].display(), candidates[1].display()));
+        self.diagnostics.push(error);
+        None
+    }
+}
+
+fn canonical(name: &str) -> PathBuf {
+    std::fs::canonicalize(name).unwrap_or_else(|_| PathBuf::from(name))
+}
@@ -0,0 +1,442 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{ModuleGraph, ModuleId, Name, ResolveError, Target};
+use crate::lexer::token::{Token, TokenKind};
+use crate::parser::ast::*;
+use crate::source_map::SourceMap;
+use crate::span::Span;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    Unresolved,
+    Resolving,
+    Resolved,
+}
+
+struct Resolver<'a> {
+    graph: &'a mut ModuleGraph,
+    sources: &'a SourceMap,
+    // Whether each module's imports have been resolved.
+    states: Vec<State>,
+    // The imports being resolved, innermost last, for reporting cycles.
+    stack: Vec<(ModuleId, Span)>,
+    // Names whose declaration or import failed. Looking one up fails without reporting it again.
+    poisoned: HashSet<(ModuleId, String)>,
+    // Modules with a failed glob import, which any missing name might have come from.
+    poisoned_globs: HashSet<ModuleId>,
+    // Names that came from a glob import, which an explicit import replaces.
+    globbed: HashSet<(ModuleId, String)>,
+    errors: Vec<ResolveError>,
+}
+
+/// Declares the top-level names of every module, resolves their imports and path expressions,
+/// and works out the order the modules run in.
+pub(super) fn resolve(graph: &mut ModuleGraph, sources: &SourceMap) -> Vec<ResolveError> {
+    let count = graph.modules.len();
+    let mut resolver = Resolver {
+        graph,
+        sources,
+        states: vec![State::Unresolved; count],
+        stack: Vec::new(),
+        poisoned: HashSet::new(),
+        poisoned_globs: HashSet::new(),
+        globbed: HashSet::new(),
+        errors: Vec::new(),
+    };
+    for id in (0..count).map(ModuleId) {
+        resolver.declare(id);
+    }
+    for id in (0..count).map(ModuleId) {
+        resolver.imports(id);
+    }
+    let mut uses: Vec<Vec<ModuleId>> = vec![Vec::new(); count];
+    for id in (0..count).map(ModuleId) {
+        uses[id.0] = resolver.paths(id);
+    }
+    resolver.graph.order = order(resolver.graph, &uses);
+    resolver.errors
+}
+
+impl Resolver<'_> {
+    fn declare(&mut self, id: ModuleId) {
+        let module = &self.graph.modules[id.0];
+        let mut names = vec![];
+        for stmt in &module.ast {
+            let (ident, public, target) = match stmt {
+                AbstractStatement::FunctionDecl(decl) => (decl.ident, decl.public, None),
+                AbstractStatement::Let(let_) => (let_.ident, let_.public, None),
+                AbstractStatement::Module(decl) => {
+                    let child = module.children[self.sources.span_str(decl.ident.span)];
+                    (decl.ident, decl.public, Some(Target::Module(child)))
+                }
+                _ => continue,
+            };
+            let name = self.sources.span_str(ident.span);
+            let target = target.unwrap_or_else(|| Target::Global(id, module.qualify(name)));
+            names.push((String::from(name), Name { target, public, span: ident.span }));
+        }
+        for (name, declared) in names {
+            self.bind(id, name, declared);
+        }
+    }
+
+    // Adds `name` to the module, unless it already refers to something else there.
+    fn bind(&mut self, id: ModuleId, name: String, binding: Name) {
+        let names = &mut self.graph.modules[id.0].names;
+        match names.get(&name) {
+            Some(existing) if self.globbed.remove(&(id, name.clone())) || existing.target == binding.target => {
+                let public = binding.public || existing.public && existing.target == binding.target;
+                names.insert(name, Name { public, ..binding });
+            }
+            Some(existing) => {
+                let span = existing.span;
+                self.errors.push(ResolveError {
+                    span: binding.span,
+                    details: format!("`{}` is defined more than once in this module.", name),
+                    related: vec![(span, String::from("first defined here"))],
+                });
+            }
+            None => {
+                names.insert(name, binding);
+            }
+        }
+    }
+
+    fn imports(&mut self, id: ModuleId) {
+        if self.states[id.0] != State::Unresolved {
+            return;
+        }
+        self.states[id.0] = State::Resolving;
+        let uses: Vec<Use> = self.graph.modules[id.0]
+            .ast
+            .iter()
+            .filter_map(|stmt| match stmt {
+                AbstractStatement::Use(use_) => Some(use_.clone()),
+                _ => None,
+            })
+            .collect();
+        for use_ in &uses {
+            self.stack.push((id, use_.path.span()));
+            self.import(id, use_);
+            self.stack.pop();
+        }
+        self.states[id.0] = State::Resolved;
+    }
+
+    fn import(&mut self, id: ModuleId, use_: &Use) {
+        let target = self.path(id, &use_.path.segments);
+        match use_.kind {
+            UseKind::Single { .. } => {
+                let binding = use_.binding().expect("single import without a name");
+                let name = String::from(self.sources.span_str(binding.span));
+                if binding.kind != TokenKind::Ident {
+                    self.error(binding.span, format!("Importing `{}` needs a name, like `use {} as name;`.", name, name));
+                    return;
+                }
+                match target {
+                    Some(target) => self.bind(id, name, Name { target, public: use_.public, span: binding.span }),
+                    None => {
+                        self.poisoned.insert((id, name));
+                    }
+                }
+            }
+            UseKind::Glob(star) => match target {
+                Some(Target::Module(from)) if self.resolved(from) => self.glob(id, from, use_.public, star.span),
+                Some(Target::Global(..)) => {
+                    let path = self.sources.span_str(use_.path.span());
+                    self.error(use_.path.span(), format!("Cannot import everything from `{}`, because it isn't a module.", path));
+                    self.poisoned_globs.insert(id);
+                }
+                _ => {
+                    self.poisoned_globs.insert(id);
+                }
+            },
+        }
+    }
+
+    // Imports every name in `from` that `id` can see.
+    fn glob(&mut self, id: ModuleId, from: ModuleId, public: bool, span: Span) {
+        let mut names: Vec<(&String, &Name)> = self.graph.modules[from.0].names.iter().collect();
+        // Sorted so that clashes between two globs are decided the same way every time.
+        names.sort_by_key(|(name, _)| *name);
+        let visible: Vec<(String, Target)> = names
+            .into_iter()
+            .filter(|(_, name)| name.public || self.is_within(id, from))
+            .map(|(name, binding)| (name.clone(), binding.target.clone()))
+            .collect();
+        for (name, target) in visible {
+            let names = &mut self.graph.modules[id.0].names;
+            if !names.contains_key(&name) {
+                names.insert(name.clone(), Name { target, public, span });
+                self.globbed.insert((id, name));
+            }
+        }
+        if self.poisoned_globs.contains(&from) {
+            self.poisoned_globs.insert(id);
+        }
+        let poisoned: Vec<String> = self.poisoned.iter().filter(|(module, _)| *module == from).map(|(_, name)| name.clone()).collect();
+        for name in poisoned {
+            self.poisoned.insert((id, name));
+        }
+    }
+
+    // Finds what a path used in module `id` refers to, reporting an error if it can't be found.
+    fn path(&mut self, id: ModuleId, segments: &[Token]) -> Option<Target> {
+        let mut target = Target::Module(id);
+        let mut leading = true;
+        for (i, segment) in segments.iter().enumerate() {
+            let Target::Module(current) = target else {
+                let previous = self.sources.span_str(segments[i - 1].span);
+                self.error(segments[i - 1].span, format!("`{}` is not a module.", previous));
+                return None;
+            };
+            target = match segment.kind {
+                TokenKind::Crate if i == 0 => Target::Module(self.graph.root()),
+                TokenKind::Super if leading => match self.graph.module(current).parent {
+                    Some(parent) => Target::Module(parent),
+                    None => {
+                        self.error(segment.span, String::from("There is no module above the crate root."));
+                        return None;
+                    }
+                },
+                TokenKind::Crate | TokenKind::Super => {
+                    self.error(segment.span, format!("`{}` can only appear at the start of a path.", segment.kind));
+                    return None;
+                }
+                _ if i == 0 => self.lookup(id, *segment)?,
+                _ => self.member(id, current, *segment)?,
+            };
+            leading = leading && segment.kind == TokenKind::Super;
+        }
+        Some(target)
+    }
+
+    // Looks up the first segment of a path among the names already declared or imported in `id`.
+    fn lookup(&mut self, id: ModuleId, segment: Token) -> Option<Target> {
+        let name = self.sources.span_str(segment.span);
+        if let Some(binding) = self.graph.module(id).names.get(name) {
+            return Some(binding.target.clone());
+        }
+        if !self.is_poisoned(id, name) {
+            self.error(segment.span, format!("Cannot find `{}` in this module.", name));
+        }
+        None
+    }
+
+    // Looks up `segment` inside module `from`, on behalf of module `id`.
+    fn member(&mut self, id: ModuleId, from: ModuleId, segment: Token) -> Option<Target> {
+        let name = self.sources.span_str(segment.span);
+        // Declared names are there from the start, but imports have to be resolved first.
+        if !self.graph.module(from).names.contains_key(name) && !self.resolved(from) {
+            return None;
+        }
+        let module = self.graph.module(from);
+        let Some(binding) = module.names.get(name) else {
+            if !self.is_poisoned(from, name) {
+                let details = format!("Cannot find `{}` in module `{}`.", name, module.display_name());
+                self.error(segment.span, details);
+            }
+            return None;
+        };
+        if !binding.public && !self.is_within(id, from) {
+            let error = ResolveError {
+                span: segment.span,
+                details: format!("`{}` is private to module `{}`.", name, module.display_name()),
+                related: vec![(binding.span, String::from("declared here without `pub`"))],
+            };
+            self.errors.push(error);
+        }
+        Some(binding.target.clone())
+    }
+
+    // Makes sure the imports of `from` are resolved, returning false after reporting a cycle if
+    // they are still being resolved further up.
+    fn resolved(&mut self, from: ModuleId) -> bool {
+        match self.states[from.0] {
+            State::Resolved => true,
+            State::Unresolved => {
+                self.imports(from);
+                true
+            }
+            State::Resolving => {
+                self.cycle(from);
+                false
+            }
+        }
+    }
+
+    fn cycle(&mut self, from: ModuleId) {
+        let start = self.stack.iter().position(|(module, _)| *module == from).expect("module isn't being resolved");
+        let cycle = &self.stack[start..];
+        let mut modules: Vec<String> = cycle.iter().map(|(module, _)| format!("`{}`", self.graph.module(*module).display_name())).collect();
+        modules.dedup();
+        modules.push(format!("`{}`", self.graph.module(from).display_name()));
+        let (_, span) = *cycle.last().expect("empty import cycle");
+        let related = cycle[..cycle.len() - 1]
+            .iter()
+            .map(|(module, span)| (*span, format!("which needs this import in `{}`", self.graph.module(*module).display_name())))
+            .collect();
+        self.errors.push(ResolveError { span, details: format!("Import cycle: {}.", modules.join(" -> ")), related });
+    }
+
+    // Whether code in `id` is inside module `ancestor`, and so can see its private names.
+    fn is_within(&self, id: ModuleId, ancestor: ModuleId) -> bool {
+        let mut current = Some(id);
+        while let Some(module) = current {
+            if module == ancestor {
+                return true;
+            }
+            current = self.graph.module(module).parent;
+        }
+        false
+    }
+
+    fn is_poisoned(&self, id: ModuleId, name: &str) -> bool {
+        self.poisoned_globs.contains(&id) || self.poisoned.contains(&(id, String::from(name)))
+    }
+
+    // Resolves every path expression in module `id`, returning the other modules it uses.
+    fn paths(&mut self, id: ModuleId) -> Vec<ModuleId> {
+        let mut found = vec![];
+        for stmt in &self.graph.module(id).ast {
+            statement_paths(stmt, &mut found);
+        }
+        let paths: Vec<Path> = found.into_iter().cloned().collect();
+        let mut uses: Vec<ModuleId> = self.graph.module(id).names.values().filter_map(|name| match name.target {
+            Target::Global(module, _) => Some(module),
+            Target::Module(_) => None,
+        }).collect();
+        for path in paths {
+            match self.path(id, &path.segments) {
+                Some(Target::Global(module, qualified)) => {
+                    uses.push(module);
+                    self.graph.paths.insert(path.span(), qualified);
+                }
+                Some(Target::Module(_)) => {
+                    let text = self.sources.span_str(path.span());
+                    self.error(path.span(), format!("Expected a value, but `{}` is a module.", text));
+                }
+                None => (),
+            }
+        }
+        uses.retain(|module| *module != id);
+        uses.sort_unstable();
+        uses.dedup();
+        uses
+    }
+
+    fn error(&mut self, span: Span, details: String) {
+        self.errors.push(ResolveError { span, details, related: Vec::new() });
+    }
+}
+
+// Orders the modules so each one runs after the modules it uses, starting from the root. Modules
+// that use each other run in the order they were declared.
+fn order(graph: &ModuleGraph, uses: &[Vec<ModuleId>]) -> Vec<ModuleId> {
+    fn visit(id: ModuleId, uses: &[Vec<ModuleId>], seen: &mut HashMap<ModuleId, ()>, order: &mut Vec<ModuleId>) {
+        if seen.insert(id, ()).is_some() {
+            return;
+        }
+        for &used in &uses[id.0] {
+            visit(used, uses, seen, order);
+        }
+        order.push(id);
+    }
+    let mut seen = HashMap::new();
+    let mut order = vec![];
+    for (id, _) in graph.modules() {
+        visit(id, uses, &mut seen, &mut order);
+    }
+    order
+}
+
+fn statement_paths<'a>(stmt: &'a AbstractStatement, paths: &mut Vec<&'a Path>) {
+    match stmt {
+        AbstractStatement::Expr(expr) => expression_paths(expr, paths),
+        AbstractStatement::Block(block) => block_paths(block, paths),
+        AbstractStatement::FunctionDecl(decl) => block_paths(&decl.body, paths),
+        AbstractStatement::Let(let_) => expression_paths(&let_.value, paths),
+        AbstractStatement::Return(ret) => {
+            if let Some(value) = &ret.value {
+                expression_paths(value, paths);
+            }
+        }
+        AbstractStatement::If(if_) => if_paths(if_, paths),
+        AbstractStatement::While(while_) => {
+            expression_paths(&while_.condition, paths);
+            block_paths(&while_.body, paths);
+        }
+        AbstractStatement::For(for_) => {
+            expression_paths(&for_.iterable, paths);
+            block_paths(&for_.body, paths);
+        }
+        AbstractStatement::Break(_)
+        | AbstractStatement::Continue(_)
+        | AbstractStatement::Module(_)
+        | AbstractStatement::Use(_)
+        | AbstractStatement::Error(_) => (),
+    }
+}
+
+fn block_paths<'a>(block: &'a Block, paths: &mut Vec<&'a Path>) {
+    for stmt in &block.stmts {
+        statement_paths(stmt, paths);
+    }
+    if let Some(value) = &block.value {
+        expression_paths(value, paths);
+    }
+}
+
+fn if_paths<'a>(if_: &'a If, paths: &mut Vec<&'a Path>) {
+    expression_paths(&if_.condition, paths);
+    block_paths(&if_.then, paths);
+    match &if_.otherwise {
+        Some(Else::If(nested)) => if_paths(nested, paths),
+        Some(Else::Block(block)) => block_paths(block, paths),
+        None => (),
+    }
+}
+
+fn expression_paths<'a>(expr: &'a AbstractExpression, paths: &mut Vec<&'a Path>) {
+    match expr {
+        AbstractExpression::Grouping(inner) => expression_paths(inner, paths),
+        AbstractExpression::Binary(binary) => {
+            expression_paths(&binary.lhs, paths);
+            expression_paths(&binary.rhs, paths);
+        }
+        AbstractExpression::Literal(_) => (),
+        AbstractExpression::BlockExpression(block) | AbstractExpression::TrailingBlock(block) => block_paths(block, paths),
+        AbstractExpression::PropertyAccess(access) => {
+            if let Some(obj) = &access.obj {
+                expression_paths(obj, paths);
+            }
+        }
+        AbstractExpression::Unary(unary) => expression_paths(&unary.expr, paths),
+        AbstractExpression::Call(call) => {
+            expression_paths(&call.expr, paths);
+            for arg in &call.args {
+                expression_paths(arg, paths);
+            }
+        }
+        AbstractExpression::If(if_) => if_paths(if_, paths),
+        AbstractExpression::List(list) => {
+            for item in &list.items {
+                expression_paths(item, paths);
+            }
+        }
+        AbstractExpression::Assign(assign) => {
+            if let Some(obj) = &assign.target.obj {
+                expression_paths(obj, paths);
+            }
+            expression_paths(&assign.value, paths);
+        }
+        AbstractExpression::Format(format) => {
+            for part in &format.parts {
+                if let FormatPart::Expr(expr) = part {
+                    expression_paths(expr, paths);
+                }
+            }
+        }
+        AbstractExpression::Path(path) => paths.push(path),
+    }
+}
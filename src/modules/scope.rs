@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+
+use super::{ModuleGraph, ModuleId, ResolveError, Target};
+use crate::lexer::token::Token;
+use crate::parser::ast::*;
+use crate::source_map::SourceMap;
+use crate::span::Span;
+use crate::value::Native;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ScopeId(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScopeKind {
+    // The top level of a file. Its locals belong to the module's top-level code.
+    Module,
+    // The parameters and body of a function.
+    Function,
+    Block,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeclarationKind {
+    Let,
+    Function,
+    Parameter,
+    For,
+}
+
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub name: String,
+    pub span: Span,
+    pub kind: DeclarationKind,
+    pub mutable: bool,
+    // What uses of the name in the scope it's declared in resolve to.
+    pub resolution: Resolution,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub parent: Option<ScopeId>,
+    pub declarations: Vec<Declaration>,
+}
+
+/// Every scope in a module, with the module's top level as the root.
+#[derive(Debug, Clone, Default)]
+pub struct ScopeTree {
+    scopes: Vec<Scope>,
+}
+
+impl ScopeTree {
+    pub fn root(&self) -> ScopeId {
+        ScopeId(0)
+    }
+
+    pub fn scope(&self, id: ScopeId) -> &Scope {
+        &self.scopes[id.0]
+    }
+
+    pub fn scopes(&self) -> impl Iterator<Item = (ScopeId, &Scope)> {
+        self.scopes.iter().enumerate().map(|(id, scope)| (ScopeId(id), scope))
+    }
+
+    fn push(&mut self, kind: ScopeKind, parent: Option<ScopeId>) -> ScopeId {
+        self.scopes.push(Scope { kind, parent, declarations: Vec::new() });
+        ScopeId(self.scopes.len() - 1)
+    }
+}
+
+// A top-level declaration, which other modules may import.
+struct Global {
+    mutable: bool,
+    span: Span,
+}
+
+/// Binds every name used in every module to its declaration, filling in the resolutions and local
+/// indices in the ASTs. Returns the errors, then warnings about shadowed names.
+pub(super) fn resolve(graph: &mut ModuleGraph, sources: &SourceMap) -> (Vec<ResolveError>, Vec<ResolveError>) {
+    let mut globals = HashMap::new();
+    for (_, module) in graph.modules() {
+        for stmt in &module.ast {
+            let (ident, mutable) = match stmt {
+                AbstractStatement::Let(let_) => (let_.ident, let_.mutable),
+                AbstractStatement::FunctionDecl(decl) => (decl.ident, false),
+                _ => continue,
+            };
+            globals.insert(module.qualify(sources.span_str(ident.span)), Global { mutable, span: ident.span });
+        }
+    }
+
+    let (mut errors, mut warnings) = (vec![], vec![]);
+    for id in (0..graph.modules.len()).map(ModuleId) {
+        let mut ast = std::mem::take(&mut graph.modules[id.0].ast);
+        let mut resolver = Resolver {
+            graph,
+            sources,
+            globals: &globals,
+            module: id,
+            tree: ScopeTree::default(),
+            scope: ScopeId(0),
+            functions: vec![FunctionScope::default()],
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        };
+        resolver.tree.push(ScopeKind::Module, None);
+        for stmt in &mut ast {
+            resolver.statement(stmt);
+        }
+        let Resolver { tree, errors: module_errors, warnings: module_warnings, .. } = resolver;
+        errors.extend(module_errors);
+        warnings.extend(module_warnings);
+        let module = &mut graph.modules[id.0];
+        module.ast = ast;
+        module.scopes = tree;
+    }
+    (errors, warnings)
+}
+
+// A function being resolved.
+#[derive(Default)]
+struct FunctionScope {
+    // The number of locals declared so far.
+    locals: usize,
+    captures: Vec<Capture>,
+}
+
+struct Resolver<'a> {
+    graph: &'a ModuleGraph,
+    sources: &'a SourceMap,
+    globals: &'a HashMap<String, Global>,
+    module: ModuleId,
+    tree: ScopeTree,
+    scope: ScopeId,
+    // Each function being resolved, innermost last. The first is the module's top-level code.
+    functions: Vec<FunctionScope>,
+    errors: Vec<ResolveError>,
+    warnings: Vec<ResolveError>,
+}
+
+impl Resolver<'_> {
+    fn statement(&mut self, stmt: &mut AbstractStatement) {
+        match stmt {
+            AbstractStatement::Expr(expr) => self.expression(expr),
+            AbstractStatement::Block(block) => self.block(block),
+            AbstractStatement::FunctionDecl(decl) => {
+                // Declared before the body, so the function can refer to itself.
+                decl.local = self.declare(decl.ident, DeclarationKind::Function, false);
+                self.begin(ScopeKind::Function);
+                self.functions.push(FunctionScope::default());
+                for arg in &decl.arguments {
+                    self.declare(arg.ident, DeclarationKind::Parameter, false);
+                }
+                self.block_contents(&mut decl.body);
+                decl.captures = self.functions.pop().expect("no function").captures;
+                self.end();
+            }
+            AbstractStatement::Let(let_) => {
+                self.expression(&mut let_.value);
+                let_.local = self.declare(let_.ident, DeclarationKind::Let, let_.mutable);
+            }
+            AbstractStatement::Return(ret) => {
+                if let Some(value) = &mut ret.value {
+                    self.expression(value);
+                }
+            }
+            AbstractStatement::If(if_) => self.if_else(if_),
+            AbstractStatement::While(while_) => {
+                self.expression(&mut while_.condition);
+                self.block(&mut while_.body);
+            }
+            AbstractStatement::For(for_) => {
+                self.expression(&mut for_.iterable);
+                self.begin(ScopeKind::Block);
+                for_.local = self.declare(for_.binding, DeclarationKind::For, false);
+                self.block(&mut for_.body);
+                self.end();
+            }
+            AbstractStatement::Break(_)
+            | AbstractStatement::Continue(_)
+            | AbstractStatement::Module(_)
+            | AbstractStatement::Use(_)
+            | AbstractStatement::Error(_) => (),
+        }
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        self.begin(ScopeKind::Block);
+        self.block_contents(block);
+        self.end();
+    }
+
+    fn block_contents(&mut self, block: &mut Block) {
+        for stmt in &mut block.stmts {
+            self.statement(stmt);
+        }
+        if let Some(value) = &mut block.value {
+            self.expression(value);
+        }
+    }
+
+    fn if_else(&mut self, if_: &mut If) {
+        self.expression(&mut if_.condition);
+        self.block(&mut if_.then);
+        match &mut if_.otherwise {
+            Some(Else::If(nested)) => self.if_else(nested),
+            Some(Else::Block(block)) => self.block(block),
+            None => (),
+        }
+    }
+
+    fn expression(&mut self, expr: &mut AbstractExpression) {
+        match expr {
+            AbstractExpression::Grouping(inner) => self.expression(inner),
+            AbstractExpression::Binary(binary) => {
+                self.expression(&mut binary.lhs);
+                self.expression(&mut binary.rhs);
+            }
+            AbstractExpression::Literal(_) | AbstractExpression::Path(_) => (),
+            AbstractExpression::BlockExpression(block) | AbstractExpression::TrailingBlock(block) => self.block(block),
+            AbstractExpression::PropertyAccess(access) => match &mut access.obj {
+                Some(obj) => self.expression(obj),
+                None => access.resolution = self.lookup(access.property),
+            },
+            AbstractExpression::Unary(unary) => self.expression(&mut unary.expr),
+            AbstractExpression::Call(call) => {
+                self.expression(&mut call.expr);
+                for arg in &mut call.args {
+                    self.expression(arg);
+                }
+            }
+            AbstractExpression::If(if_) => self.if_else(if_),
+            AbstractExpression::List(list) => {
+                for item in &mut list.items {
+                    self.expression(item);
+                }
+            }
+            AbstractExpression::Assign(assign) => {
+                let target = &mut assign.target;
+                match &mut target.obj {
+                    Some(obj) => {
+                        self.expression(obj);
+                        // No value has properties yet, so the backends can't store one.
+                        let name = self.sources.span_str(target.property.span);
+                        self.error(target.property.span, format!("Cannot assign to property `{}`, values have no properties.", name));
+                    }
+                    None => {
+                        target.resolution = self.lookup(target.property);
+                        self.check_assignable(target.property, target.resolution.as_ref());
+                    }
+                }
+                self.expression(&mut assign.value);
+            }
+            AbstractExpression::Format(format) => {
+                for part in &mut format.parts {
+                    if let FormatPart::Expr(expr) = part {
+                        self.expression(expr);
+                    }
+                }
+            }
+        }
+    }
+
+    // Adds a declaration to the current scope, returning the index of the local it declares.
+    fn declare(&mut self, ident: Token, kind: DeclarationKind, mutable: bool) -> Option<usize> {
+        let name = self.sources.span_str(ident.span);
+        if self.scope == self.tree.root() {
+            // Top-level declarations are globals, which were declared when resolving imports.
+            let resolution = Resolution::Global(self.graph.module(self.module).qualify(name));
+            self.add(Declaration { name: String::from(name), span: ident.span, kind, mutable, resolution });
+            return None;
+        }
+
+        if let Some((shadowed, _)) = self.find_local(name) {
+            let span = shadowed.span;
+            self.warnings.push(ResolveError {
+                span: ident.span,
+                details: format!("`{}` shadows an earlier declaration.", name),
+                related: vec![(span, String::from("shadowed declaration here"))],
+            });
+        }
+        let function = self.functions.last_mut().expect("no function");
+        let local = function.locals;
+        function.locals += 1;
+        self.add(Declaration { name: String::from(name), span: ident.span, kind, mutable, resolution: Resolution::Local(local) });
+        Some(local)
+    }
+
+    fn add(&mut self, declaration: Declaration) {
+        self.tree.scopes[self.scope.0].declarations.push(declaration);
+    }
+
+    // Finds what `ident` refers to from the current scope, reporting an error if nothing does.
+    fn lookup(&mut self, ident: Token) -> Option<Resolution> {
+        let name = self.sources.span_str(ident.span);
+        if let Some((declaration, crossed)) = self.find_local(name) {
+            return Some(match declaration.resolution.clone() {
+                Resolution::Local(local) if crossed > 0 => {
+                    let function = self.functions.len() - 1;
+                    Resolution::Capture(self.capture(function, crossed, local))
+                }
+                resolution => resolution,
+            });
+        }
+        match self.graph.module(self.module).names.get(name).map(|binding| &binding.target) {
+            Some(Target::Global(_, qualified)) => return Some(Resolution::Global(qualified.clone())),
+            Some(Target::Module(_)) => {
+                self.error(ident.span, format!("Expected a value, but `{}` is a module.", name));
+                return None;
+            }
+            None => (),
+        }
+        if Native::from_name(name).is_some() {
+            return Some(Resolution::Builtin);
+        }
+        self.error(ident.span, format!("Cannot find `{}` in this scope.", name));
+        None
+    }
+
+    // Captures `local` of the function `crossed` levels out from `function`, through every function
+    // in between. Returns its index in the captures of `function`.
+    fn capture(&mut self, function: usize, crossed: usize, local: usize) -> usize {
+        let capture = match crossed {
+            1 => Capture::Local(local),
+            _ => Capture::Enclosing(self.capture(function - 1, crossed - 1, local)),
+        };
+        let captures = &mut self.functions[function].captures;
+        captures.iter().position(|&other| other == capture).unwrap_or_else(|| {
+            captures.push(capture);
+            captures.len() - 1
+        })
+    }
+
+    // Finds the innermost local called `name`, and how many enclosing functions out it belongs to.
+    fn find_local(&self, name: &str) -> Option<(&Declaration, usize)> {
+        let mut crossed = 0;
+        let mut current = Some(self.scope);
+        while let Some(id) = current {
+            let scope = self.tree.scope(id);
+            if scope.kind == ScopeKind::Module {
+                // Top-level declarations are globals, which are looked up in the module instead.
+                return None;
+            }
+            if let Some(declaration) = scope.declarations.iter().rev().find(|declaration| declaration.name == name) {
+                return Some((declaration, crossed));
+            }
+            if scope.kind == ScopeKind::Function {
+                crossed += 1;
+            }
+            current = scope.parent;
+        }
+        None
+    }
+
+    fn check_assignable(&mut self, ident: Token, resolution: Option<&Resolution>) {
+        let name = self.sources.span_str(ident.span);
+        let declared = match resolution {
+            Some(Resolution::Local(_) | Resolution::Capture(_)) => {
+                let (declaration, _) = self.find_local(name).expect("resolved local is missing");
+                (!declaration.mutable).then_some(declaration.span)
+            }
+            Some(Resolution::Global(qualified)) => {
+                let global = &self.globals[qualified];
+                (!global.mutable).then_some(global.span)
+            }
+            Some(Resolution::Builtin) => {
+                self.error(ident.span, format!("Cannot assign to builtin `{}`.", name));
+                None
+            }
+            None => None,
+        };
+        if let Some(span) = declared {
+            self.errors.push(ResolveError {
+                span: ident.span,
+                details: format!("Cannot assign to immutable variable `{}`.", name),
+                related: vec![(span, String::from("declared here without `mut`"))],
+            });
+        }
+    }
+
+    fn begin(&mut self, kind: ScopeKind) {
+        self.scope = self.tree.push(kind, Some(self.scope));
+    }
+
+    fn end(&mut self) {
+        self.scope = self.tree.scope(self.scope).parent.expect("ended the module scope");
+    }
+
+    fn error(&mut self, span: Span, details: String) {
+        self.errors.push(ResolveError { span, details, related: Vec::new() });
+    }
+}
@@ -1,70 +1,321 @@
 use crate::lexer::token::Token;
+use crate::span::Span;
 
 pub type Ast = Vec<AbstractStatement>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AbstractStatement {
     Expr(AbstractExpression),
     // A block can appear without an expression statement
     Block(Block),
     FunctionDecl(FunctionDecl),
-    Return(AbstractExpression),
+    Let(Let),
+    Return(Return),
+    If(If),
+    While(While),
+    For(For),
+    Break(LoopControl),
+    Continue(LoopControl),
+    Module(ModuleDecl),
+    Use(Use),
+    // A statement that failed to parse. The error has already been reported.
+    Error(Span),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AbstractExpression {
     Grouping(Box<AbstractExpression>),
     Binary(Binary),
-    Literal(AbstractLiteral),
+    Literal(Literal),
     // Inline block expression
     // Example:
     // my_fn({ return 2+2; })
     BlockExpression(Block),
+    // A block statement at the end of a block, which gives that block its value. Unlike a block
+    // expression, a `return` inside it returns from the function.
+    TrailingBlock(Block),
     PropertyAccess(PropertyAccess),
     Unary(Unary),
     Call(Call),
+    If(If),
+    List(List),
+    Assign(Assign),
+    Format(Format),
+    Path(Path),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub struct Literal {
+    pub token: Token,
+    pub value: AbstractLiteral,
+}
+
+#[derive(Debug, Clone)]
 pub enum AbstractLiteral {
+    Bool(bool),
     UInt(u64),
+    Int(i64),
+    Float(f64),
     String(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PropertyAccess {
     // None means the current environment
     pub obj: Option<Box<AbstractExpression>>,
     pub property: Token,
+    // What a name in the current environment refers to, filled in by the resolver.
+    pub resolution: Option<Resolution>,
+}
+
+/// What a name refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    // A local of the function the name is used in. Each function numbers its locals in the order
+    // they are declared, starting with its parameters.
+    Local(usize),
+    // A local of an enclosing function, by its index in the function's captures.
+    Capture(usize),
+    // A top-level declaration or import, by its qualified name.
+    Global(String),
+    // A native function like `print`.
+    Builtin,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FunctionDecl {
+    // The `///` comments directly before the declaration.
+    pub docs: Vec<Token>,
+    pub public: bool,
     pub ident: Token,
-    pub arguments: Vec<Token>,
+    pub arguments: Vec<Parameter>,
+    pub returns: Option<TypeExpr>,
     pub body: Block,
+    // The local the function is stored in, filled in by the resolver. None at the top level.
+    pub local: Option<usize>,
+    // The locals of enclosing functions that the body uses, filled in by the resolver.
+    pub captures: Vec<Capture>,
+}
+
+/// Where a function gets a captured local from when it is declared.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Capture {
+    // A local of the function the declaration is in, by its index.
+    Local(usize),
+    // One of the captures of the function the declaration is in.
+    Enclosing(usize),
+}
+
+impl FunctionDecl {
+    /// The lines of the function's doc comments, without their leading `///`.
+    pub fn doc_lines<'src>(&self, src: &'src str) -> Vec<&'src str> {
+        self.docs
+            .iter()
+            .map(|doc| {
+                let line = &src[doc.span.range()][3..];
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                line.strip_prefix(' ').unwrap_or(line)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub ident: Token,
+    pub ty: Option<TypeExpr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Let {
+    pub keyword: Token,
+    pub public: bool,
+    pub mutable: bool,
+    pub ident: Token,
+    pub ty: Option<TypeExpr>,
+    pub value: AbstractExpression,
+    // The local it declares, filled in by the resolver. None at the top level.
+    pub local: Option<usize>,
+}
+
+// A type annotation, like `int`, `[string]` or `fun(int) -> bool`.
+#[derive(Debug, Clone)]
+pub struct TypeExpr {
+    pub span: Span,
+    pub kind: TypeExprKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeExprKind {
+    Named(Token),
+    List(Box<TypeExpr>),
+    // No return type means the function returns unit.
+    Function { params: Vec<TypeExpr>, returns: Option<Box<TypeExpr>> },
+}
+
+// `mod name;`, whose contents are in `name.cir` or `name/mod.cir`.
+#[derive(Debug, Clone)]
+pub struct ModuleDecl {
+    pub keyword: Token,
+    pub public: bool,
+    pub ident: Token,
+}
+
+#[derive(Debug, Clone)]
+pub struct Use {
+    pub keyword: Token,
+    pub public: bool,
+    pub path: Path,
+    pub kind: UseKind,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub enum UseKind {
+    // Imports the last segment of the path, under another name if it has an alias.
+    Single { alias: Option<Token> },
+    // `path::*` imports every name in the module that is visible from the importing one.
+    Glob(Token),
+}
+
+impl Use {
+    /// The name a single import is bound to, or None for a glob import.
+    pub fn binding(&self) -> Option<Token> {
+        match self.kind {
+            UseKind::Single { alias } => alias.or(self.path.segments.last().copied()),
+            UseKind::Glob(_) => None,
+        }
+    }
+}
+
+// Names separated by `::`. A path may start with `crate` or any number of `super`s.
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub segments: Vec<Token>,
+}
+
+impl Path {
+    pub fn span(&self) -> Span {
+        let first = self.segments.first().expect("empty path").span;
+        let last = self.segments.last().expect("empty path").span;
+        Span { end: last.end, ..first }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Return {
+    pub keyword: Token,
+    pub value: Option<AbstractExpression>,
+}
+
+#[derive(Debug, Clone)]
+pub struct If {
+    pub keyword: Token,
+    pub condition: Box<AbstractExpression>,
+    pub then: Block,
+    pub otherwise: Option<Else>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Else {
+    If(Box<If>),
+    Block(Block),
+}
+
+#[derive(Debug, Clone)]
+pub struct While {
+    pub label: Option<Token>,
+    pub keyword: Token,
+    pub condition: Box<AbstractExpression>,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone)]
+pub struct For {
+    pub label: Option<Token>,
+    pub keyword: Token,
+    pub binding: Token,
+    pub iterable: Box<AbstractExpression>,
+    pub body: Block,
+    // The local of the binding, filled in by the resolver.
+    pub local: Option<usize>,
+}
+
+// `break` or `continue`. No label means the innermost loop.
+#[derive(Debug, Clone)]
+pub struct LoopControl {
+    pub keyword: Token,
+    pub label: Option<Token>,
+}
+
+#[derive(Debug, Clone)]
+pub struct List {
+    pub bracket: Token,
+    pub items: Vec<AbstractExpression>,
+}
+
+// A string with `{expr}` interpolations. Each part is converted to a string and they're joined.
+#[derive(Debug, Clone)]
+pub struct Format {
+    pub string: Token,
+    pub parts: Vec<FormatPart>,
+}
+
+#[derive(Debug, Clone)]
+pub enum FormatPart {
+    Text(String),
+    Expr(AbstractExpression),
+}
+
+// `=` or a compound assignment like `+=`.
+#[derive(Debug, Clone)]
+pub struct Assign {
+    pub operator: Token,
+    pub target: PropertyAccess,
+    pub value: Box<AbstractExpression>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Unary {
     pub op: Token,
     pub expr: Box<AbstractExpression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Call {
     pub expr: Box<AbstractExpression>,
     pub args: Vec<AbstractExpression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Block {
     pub stmts: Ast,
+    // A trailing expression without a semicolon is the value of the block.
+    pub value: Option<Box<AbstractExpression>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Binary {
     pub operator: Token,
     pub lhs: Box<AbstractExpression>,
     pub rhs: Box<AbstractExpression>,
-}
\ No newline at end of file
+}
+
+impl AbstractExpression {
+    /// The span of the token that best identifies this expression, if it has one.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            AbstractExpression::Grouping(inner) => inner.span(),
+            AbstractExpression::Binary(binary) => Some(binary.operator.span),
+            AbstractExpression::Literal(literal) => Some(literal.token.span),
+            AbstractExpression::BlockExpression(_) | AbstractExpression::TrailingBlock(_) => None,
+            AbstractExpression::PropertyAccess(access) => Some(access.property.span),
+            AbstractExpression::Unary(unary) => Some(unary.op.span),
+            AbstractExpression::Call(call) => call.expr.span(),
+            AbstractExpression::If(if_) => Some(if_.keyword.span),
+            AbstractExpression::List(list) => Some(list.bracket.span),
+            AbstractExpression::Assign(assign) => Some(assign.operator.span),
+            AbstractExpression::Format(format) => Some(format.string.span),
+            AbstractExpression::Path(path) => Some(path.span()),
+        }
+    }
+}
@@ -9,25 +9,50 @@ use crate::span::Span;
 pub mod ast;
 mod parse;
 
+pub use parse::program;
 pub use parse::statement;
+pub use parse::statements;
 pub use parse::expression;
 
-pub type Result<T> = std::result::Result<T, &'static str>;
+pub type Result<T> = std::result::Result<T, ParseError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    // The span of the offending token, or an empty span at the end of the input.
+    pub span: Span,
+    // None means the end of the input was reached.
+    pub found: Option<TokenKind>,
+    // The token kinds that would have been accepted instead.
+    pub expected: Vec<TokenKind>,
+    pub details: String,
+}
 
 pub struct ParseStream<'src> {
     tokens: TokenStream,
     lexeme: &'src str,
+    errors: Vec<ParseError>,
+    // How many function bodies and block expressions, which `return` can exit, the parser is inside.
+    return_depth: usize,
+    // How many blocks of any kind the parser is inside.
+    block_depth: usize,
+    // The labels of the loops around the current statement, innermost last.
+    loops: Vec<Option<&'src str>>,
 }
 
 impl<'src> ParseStream<'src> {
     pub fn new(tokens: TokenStream, lexeme: &'src str) -> ParseStream<'src> {
-        ParseStream { tokens, lexeme }
+        ParseStream { tokens, lexeme, errors: Vec::new(), return_depth: 0, block_depth: 0, loops: Vec::new() }
     }
 
     pub fn peek(&mut self) -> Option<Token> {
         self.tokens.peek()
     }
 
+    /// The last token taken from the stream.
+    pub fn previous(&self) -> Option<Token> {
+        self.tokens.previous()
+    }
+
     pub fn peeks(&mut self, kind: TokenKind) -> bool {
         let tok = self.tokens.peek();
         match tok {
@@ -76,13 +101,32 @@ impl<'src> ParseStream<'src> {
         None
     }
 
-    pub fn expect(&mut self, kind: TokenKind, err: &'static str) -> Result<()> {
-        let tok = self.tokens.next().ok_or(err)?;
-        if tok.kind == kind {
-            Ok(())
-        } else {
-            Err(err)
-        }
+    pub fn expect(&mut self, kind: TokenKind, err: &'static str) -> Result<Token> {
+        self.get(kind).ok_or_else(|| self.error(&[kind], err))
+    }
+
+    /// Creates an error at the next token, or at the end of the input if there are none left.
+    pub fn error(&mut self, expected: &[TokenKind], details: &str) -> ParseError {
+        let found = self.tokens.peek();
+        let span = match found {
+            Some(tok) => tok.span,
+            None => self.tokens.span().after(),
+        };
+        ParseError { span, found: found.map(|tok| tok.kind), expected: expected.to_vec(), details: String::from(details) }
+    }
+
+    /// Records an error that the parser recovered from.
+    pub fn report(&mut self, error: ParseError) {
+        self.errors.push(error);
+    }
+
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// The number of tokens left in the stream.
+    pub fn remaining(&self) -> usize {
+        self.tokens.len()
     }
 
     pub fn src(&self, range: Range<usize>) -> &'src str {
@@ -90,7 +134,7 @@ impl<'src> ParseStream<'src> {
     }
 
     pub fn src_from_span(&self, span: Span) -> &'src str {
-        &self.lexeme[span.0.index..span.1.index]
+        &self.lexeme[span.range()]
     }
 }
 
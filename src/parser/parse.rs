@@ -1,34 +1,462 @@
-use super::{ast::*, ParseStream, Result};
+use super::{ast::*, ParseError, ParseStream, Result};
+use crate::lexer::lex::{number_value, string_segments, LexError, Number, Segment};
+use crate::lexer::tokenize_span;
 use crate::lexer::token::{TokenKind, Token};
+use crate::span::Span;
 
 
 // TODO: Make a trait called FallbackParser that has a parse method just like all of these ones. vvvvv
 
-pub fn statement(stream: &mut ParseStream) -> Result<AbstractStatement> {
-    fun_decl(stream)
-} 
+/// Parses a whole file, failing with every error found. Use `statements` to keep the recovered AST.
+pub fn program(stream: &mut ParseStream) -> std::result::Result<Ast, Vec<ParseError>> {
+    let (ast, errors) = statements(stream);
+    if errors.is_empty() {
+        Ok(ast)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parses statements until the end of the input, recovering from every error along the way.
+pub fn statements(stream: &mut ParseStream) -> (Ast, Vec<ParseError>) {
+    let mut ast = vec![];
+    while let Some(tok) = stream.peek() {
+        // A closing brace can't start a statement, so at the top level it has nothing to close.
+        if tok.kind == TokenKind::RBrace {
+            let error = stream.error(&[], "Unmatched closing brace '}'.");
+            stream.report(error);
+            stream.next();
+            continue;
+        }
+        ast.push(statement(stream));
+    }
+    (ast, stream.take_errors())
+}
+
+/// Parses a statement. On failure the error is recorded in the stream, tokens are skipped until
+/// the next statement boundary and an `AbstractStatement::Error` takes the statement's place.
+pub fn statement(stream: &mut ParseStream) -> AbstractStatement {
+    let start = stream.remaining();
+    match item(stream) {
+        Ok(stmt) => stmt,
+        Err(error) => {
+            let span = error.span;
+            stream.report(error);
+            synchronize(stream, start);
+            AbstractStatement::Error(span)
+        }
+    }
+}
+
+fn synchronize(stream: &mut ParseStream, start: usize) {
+    // Always skip at least one token so a stray token can't stall the parser.
+    let mut depth = 0;
+    if stream.remaining() == start {
+        match stream.next().map(|tok| tok.kind) {
+            Some(TokenKind::Semi) => return,
+            Some(TokenKind::LBrace) => depth += 1,
+            _ => (),
+        }
+    }
+    // Blocks opened after the error are skipped as a whole, so their closing brace ends the statement.
+    while let Some(tok) = stream.peek() {
+        match tok.kind {
+            TokenKind::Semi if depth == 0 => {
+                stream.next();
+                return;
+            }
+            TokenKind::RBrace if depth == 0 => return,
+            TokenKind::Fun | TokenKind::Let | TokenKind::Mod | TokenKind::Use | TokenKind::Pub if depth == 0 => return,
+            TokenKind::LBrace => {
+                stream.next();
+                depth += 1;
+            }
+            TokenKind::RBrace => {
+                stream.next();
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            }
+            _ => {
+                stream.next();
+            }
+        }
+    }
+}
+
+const DECLARATION_START: [TokenKind; 4] = [TokenKind::Fun, TokenKind::Let, TokenKind::Mod, TokenKind::Use];
+
+// Declarations that may be marked `pub`: functions, `let`, `mod` and `use`.
+pub fn item(stream: &mut ParseStream) -> Result<AbstractStatement> {
+    // Doc comments are kept for functions and ignored before any other statement.
+    let mut docs = vec![];
+    while let Some(doc) = stream.get(TokenKind::DocComment) {
+        docs.push(doc);
+    }
+    let public = stream.get(TokenKind::Pub);
+    if let Some(public) = public.filter(|_| stream.block_depth > 0) {
+        stream.report(ParseError {
+            span: public.span,
+            found: Some(public.kind),
+            expected: vec![],
+            details: String::from("Only top-level declarations can be 'pub'."),
+        });
+    }
+    let public = public.is_some();
+    if stream.peeks(TokenKind::Fun) {
+        fun_decl(stream, docs, public)
+    } else if stream.peeks(TokenKind::Mod) {
+        module_decl(stream, public)
+    } else if stream.peeks(TokenKind::Use) {
+        use_decl(stream, public)
+    } else if public && stream.peeks(TokenKind::Let) {
+        match let_stmt(stream)? {
+            AbstractStatement::Let(let_) => Ok(AbstractStatement::Let(Let { public, ..let_ })),
+            _ => unreachable!("`let` didn't parse as a let statement"),
+        }
+    } else if public {
+        Err(stream.error(&DECLARATION_START, "Expected a declaration after 'pub'."))
+    } else if !docs.is_empty() && (stream.peek().is_none() || stream.peeks(TokenKind::RBrace)) {
+        Err(stream.error(&[TokenKind::Fun], "Expected a declaration after doc comment."))
+    } else {
+        let_stmt(stream)
+    }
+}
+
+pub fn fun_decl(stream: &mut ParseStream, docs: Vec<Token>, public: bool) -> Result<AbstractStatement> {
+    stream.expect(TokenKind::Fun, "Expected 'fun'.")?;
+    let fun_ident = expect_ident(stream)?;
+    stream.expect(TokenKind::LParen, "Expected opening parenthesis '('.")?;
+    let arguments = parameters(stream)?;
+    stream.expect(TokenKind::RParen, "Expected closing parenthesis ')' after parameters.")?;
+    let returns = if stream.gets(TokenKind::Arrow) { Some(type_expr(stream)?) } else { None };
+    // Loops outside the function can't be broken out of from inside it.
+    let loops = std::mem::take(&mut stream.loops);
+    stream.return_depth += 1;
+    let body = expect_block(stream);
+    stream.return_depth -= 1;
+    stream.loops = loops;
+
+    Ok(AbstractStatement::FunctionDecl(FunctionDecl {
+        docs,
+        public,
+        ident: fun_ident,
+        arguments,
+        returns,
+        body: body?,
+        local: None,
+        captures: Vec::new(),
+    }))
+}
+
+pub fn module_decl(stream: &mut ParseStream, public: bool) -> Result<AbstractStatement> {
+    let keyword = stream.expect(TokenKind::Mod, "Expected 'mod'.")?;
+    let ident = expect_ident(stream)?;
+    stream.expect(TokenKind::Semi, "Expected a semicolon ';' after module declaration.")?;
+    top_level_only(stream, keyword, "Modules can only be declared at the top level of a file.");
+    Ok(AbstractStatement::Module(ModuleDecl { keyword, public, ident }))
+}
+
+pub fn use_decl(stream: &mut ParseStream, public: bool) -> Result<AbstractStatement> {
+    let keyword = stream.expect(TokenKind::Use, "Expected 'use'.")?;
+    let first = stream
+        .get_any(PATH_START)
+        .ok_or_else(|| stream.error(&PATH_START, "Expected a path after 'use'."))?;
+    let mut segments = vec![first];
+    let mut glob = None;
+    while stream.gets(TokenKind::ColonColon) {
+        if let Some(star) = stream.get(TokenKind::Star) {
+            glob = Some(star);
+            break;
+        }
+        segments.push(path_segment(stream)?);
+    }
+    let kind = match glob {
+        Some(star) => UseKind::Glob(star),
+        None if stream.gets(TokenKind::As) => UseKind::Single { alias: Some(expect_ident(stream)?) },
+        None => UseKind::Single { alias: None },
+    };
+    stream.expect(TokenKind::Semi, "Expected a semicolon ';' after use declaration.")?;
+    top_level_only(stream, keyword, "'use' is only allowed at the top level of a file.");
+    Ok(AbstractStatement::Use(Use { keyword, public, path: Path { segments }, kind }))
+}
+
+// Reports `keyword` if it is inside a block. The declaration itself parsed fine, so this doesn't
+// need to trigger recovery.
+fn top_level_only(stream: &mut ParseStream, keyword: Token, details: &str) {
+    if stream.block_depth > 0 {
+        stream.report(ParseError { span: keyword.span, found: Some(keyword.kind), expected: vec![], details: String::from(details) });
+    }
+}
+
+// The tokens a path can start with. Only `super` may follow another segment, which the resolver checks.
+const PATH_START: [TokenKind; 3] = [TokenKind::Ident, TokenKind::Super, TokenKind::Crate];
+
+fn path_segment(stream: &mut ParseStream) -> Result<Token> {
+    stream.get_any(PATH_START).ok_or_else(|| stream.error(&PATH_START, "Expected a name after '::'."))
+}
+
+pub fn let_stmt(stream: &mut ParseStream) -> Result<AbstractStatement> {
+    if let Some(keyword) = stream.get(TokenKind::Let) {
+        let mutable = stream.gets(TokenKind::Mut);
+        let ident = expect_ident(stream)?;
+        let ty = if stream.gets(TokenKind::Colon) { Some(type_expr(stream)?) } else { None };
+        stream.expect(TokenKind::Eq, "Expected '=' after variable name.")?;
+        let value = expression(stream)?;
+        stream.expect(TokenKind::Semi, "Expected a semicolon ';' after variable declaration.")?;
+        Ok(AbstractStatement::Let(Let { keyword, public: false, mutable, ident, ty, value, local: None }))
+    } else {
+        return_stmt(stream)
+    }
+}
+
+pub fn return_stmt(stream: &mut ParseStream) -> Result<AbstractStatement> {
+    if let Some(keyword) = stream.get(TokenKind::Return) {
+        let value = if stream.peeks(TokenKind::Semi) { None } else { Some(expression(stream)?) };
+        stream.expect(TokenKind::Semi, "Expected a semicolon ';' after return statement.")?;
+        if stream.return_depth == 0 {
+            // The statement itself parsed fine, so this doesn't need to trigger recovery.
+            stream.report(ParseError {
+                span: keyword.span,
+                found: Some(keyword.kind),
+                expected: vec![],
+                details: String::from("Cannot return outside of a function body."),
+            });
+        }
+        Ok(AbstractStatement::Return(Return { keyword, value }))
+    } else {
+        loop_control(stream)
+    }
+}
+
+pub fn loop_control(stream: &mut ParseStream) -> Result<AbstractStatement> {
+    if let Some(keyword) = stream.get_any([TokenKind::Break, TokenKind::Continue]) {
+        let label = stream.get(TokenKind::Label);
+        stream.expect(TokenKind::Semi, "Expected a semicolon ';' after loop control statement.")?;
+        let error = match label {
+            _ if stream.loops.is_empty() => Some((keyword.span, format!("Cannot use '{}' outside of a loop.", keyword.kind))),
+            Some(label) => {
+                let name = stream.src_from_span(label.span);
+                let defined = stream.loops.contains(&Some(name));
+                (!defined).then(|| (label.span, format!("Undefined loop label `{}`.", name)))
+            }
+            None => None,
+        };
+        if let Some((span, details)) = error {
+            stream.report(ParseError { span, found: None, expected: vec![], details });
+        }
+        let control = LoopControl { keyword, label };
+        Ok(match keyword.kind {
+            TokenKind::Break => AbstractStatement::Break(control),
+            _ => AbstractStatement::Continue(control),
+        })
+    } else {
+        loop_stmt(stream)
+    }
+}
+
+pub fn loop_stmt(stream: &mut ParseStream) -> Result<AbstractStatement> {
+    let label = stream.get(TokenKind::Label);
+    if label.is_some() {
+        stream.expect(TokenKind::Colon, "Expected a colon ':' after loop label.")?;
+    }
+    if let Some(keyword) = stream.get(TokenKind::While) {
+        let condition = Box::new(expression(stream)?);
+        let body = loop_body(stream, label)?;
+        Ok(AbstractStatement::While(While { label, keyword, condition, body }))
+    } else if let Some(keyword) = stream.get(TokenKind::For) {
+        let binding = expect_ident(stream)?;
+        stream.expect(TokenKind::In, "Expected 'in' after loop variable.")?;
+        let iterable = Box::new(expression(stream)?);
+        let body = loop_body(stream, label)?;
+        Ok(AbstractStatement::For(For { label, keyword, binding, iterable, body, local: None }))
+    } else if label.is_some() {
+        Err(stream.error(&[TokenKind::While, TokenKind::For], "Expected a loop after label."))
+    } else {
+        block_stmt(stream)
+    }
+}
+
+fn loop_body(stream: &mut ParseStream, label: Option<Token>) -> Result<Block> {
+    let name = label.map(|label| stream.src_from_span(label.span));
+    stream.loops.push(name);
+    let body = expect_block(stream);
+    stream.loops.pop();
+    body
+}
+
+pub fn block_stmt(stream: &mut ParseStream) -> Result<AbstractStatement> {
+    if stream.peeks(TokenKind::LBrace) {
+        let block = expect_block(stream)?;
+        stream.gets(TokenKind::Semi);
+        Ok(AbstractStatement::Block(block))
+    } else {
+        if_stmt(stream)
+    }
+}
 
-pub fn fun_decl(stream: &mut ParseStream) -> Result<AbstractStatement> {
-    if stream.gets(TokenKind::Fun) {
-        let fun_ident = expect_ident(stream)?;
-        stream.expect(TokenKind::LParen, "Expected opening parenthesis '('.")?;
-        stream.expect(TokenKind::RParen, "Expected closing parenthesis ')'.")?;
-        let body = expect_block(stream)?;
-        
-        Ok(AbstractStatement::FunctionDecl(FunctionDecl { ident: fun_ident, arguments: vec![], body }))
+pub fn if_stmt(stream: &mut ParseStream) -> Result<AbstractStatement> {
+    if stream.peeks(TokenKind::If) {
+        let if_ = if_else(stream)?;
+        stream.gets(TokenKind::Semi);
+        Ok(AbstractStatement::If(if_))
     } else {
         expression_stmt(stream)
     }
 }
 
+pub fn if_else(stream: &mut ParseStream) -> Result<If> {
+    let keyword = stream.expect(TokenKind::If, "Expected 'if'.")?;
+    let condition = Box::new(expression(stream)?);
+    let then = expect_block(stream)?;
+    let otherwise = if stream.gets(TokenKind::Else) {
+        if stream.peeks(TokenKind::If) {
+            Some(Else::If(Box::new(if_else(stream)?)))
+        } else if stream.peeks(TokenKind::LBrace) {
+            Some(Else::Block(expect_block(stream)?))
+        } else {
+            return Err(stream.error(&[TokenKind::If, TokenKind::LBrace], "Expected 'if' or a block after 'else'."));
+        }
+    } else {
+        None
+    };
+    Ok(If { keyword, condition, then, otherwise })
+}
+
+pub fn parameters(stream: &mut ParseStream) -> Result<Vec<Parameter>> {
+    let mut params: Vec<Parameter> = vec![];
+    while !stream.peeks(TokenKind::RParen) {
+        let param = stream
+            .get(TokenKind::Ident)
+            .ok_or_else(|| stream.error(&[TokenKind::Ident, TokenKind::RParen], "Expected parameter name."))?;
+        let ty = if stream.gets(TokenKind::Colon) { Some(type_expr(stream)?) } else { None };
+        let name = stream.src_from_span(param.span);
+        if params.iter().any(|other| stream.src_from_span(other.ident.span) == name) {
+            // The parameter list is still well-formed, so keep parsing after reporting this.
+            stream.report(ParseError {
+                span: param.span,
+                found: Some(param.kind),
+                expected: vec![],
+                details: format!("Duplicate parameter `{}`.", name),
+            });
+        } else {
+            params.push(Parameter { ident: param, ty });
+        }
+        if !stream.gets(TokenKind::Comma) {
+            break;
+        }
+    }
+    Ok(params)
+}
+
+const TYPE_START: [TokenKind; 3] = [TokenKind::Ident, TokenKind::LBracket, TokenKind::Fun];
+
+// A type name, `[item]` or `fun(params) -> returns`.
+pub fn type_expr(stream: &mut ParseStream) -> Result<TypeExpr> {
+    let start = stream.get_any(TYPE_START).ok_or_else(|| stream.error(&TYPE_START, "Expected a type."))?;
+    let kind = match start.kind {
+        TokenKind::LBracket => {
+            let item = type_expr(stream)?;
+            stream.expect(TokenKind::RBracket, "Expected closing bracket ']' after list item type.")?;
+            TypeExprKind::List(Box::new(item))
+        }
+        TokenKind::Fun => {
+            stream.expect(TokenKind::LParen, "Expected opening parenthesis '(' after 'fun'.")?;
+            let mut params = vec![];
+            while !stream.peeks(TokenKind::RParen) {
+                params.push(type_expr(stream)?);
+                if !stream.gets(TokenKind::Comma) {
+                    break;
+                }
+            }
+            stream.expect(TokenKind::RParen, "Expected closing parenthesis ')' after parameter types.")?;
+            let returns = if stream.gets(TokenKind::Arrow) { Some(Box::new(type_expr(stream)?)) } else { None };
+            TypeExprKind::Function { params, returns }
+        }
+        _ => TypeExprKind::Named(start),
+    };
+    let end = stream.previous().expect("no previous token").span;
+    Ok(TypeExpr { span: Span { end: end.end, ..start.span }, kind })
+}
+
 pub fn expression_stmt(stream: &mut ParseStream) -> Result<AbstractStatement> {
     let expr = expression(stream)?;
-    stream.expect(TokenKind::Semi, "Expected a semicolon ';' after expression.")?;
+    // The last expression in a block may leave off its semicolon to become the block's value.
+    if stream.block_depth == 0 || !stream.peeks(TokenKind::RBrace) {
+        stream.expect(TokenKind::Semi, "Expected a semicolon ';' after expression.")?;
+    }
     Ok(AbstractStatement::Expr(expr))
 }
 
 pub fn expression(stream: &mut ParseStream) -> Result<AbstractExpression> {
-    add(stream)
+    assignment(stream)
+}
+
+const ASSIGNMENT_OPERATORS: [TokenKind; 5] =
+    [TokenKind::Eq, TokenKind::PlusEq, TokenKind::MinusEq, TokenKind::StarEq, TokenKind::SlashEq];
+
+pub fn assignment(stream: &mut ParseStream) -> Result<AbstractExpression> {
+    let expr = range(stream)?;
+    if let Some(operator) = stream.get_any(ASSIGNMENT_OPERATORS) {
+        let AbstractExpression::PropertyAccess(target) = expr else {
+            return Err(ParseError {
+                span: operator.span,
+                found: Some(operator.kind),
+                expected: vec![],
+                details: String::from("Invalid assignment target."),
+            });
+        };
+        // Assignment is right associative, so `a = b = c` assigns `c` to both.
+        let value = Box::new(assignment(stream)?);
+        Ok(AbstractExpression::Assign(Assign { operator, target, value }))
+    } else {
+        Ok(expr)
+    }
+}
+
+// From loosest to tightest binding: assignment, range, `||`, `&&`, equality, comparison, `+ -`,
+// `* / %` and finally unary operators. Every binary level except range is left associative.
+
+pub fn range(stream: &mut ParseStream) -> Result<AbstractExpression> {
+    let expr = or(stream)?;
+    if let Some(operator) = stream.get(TokenKind::DotDot) {
+        Ok(AbstractExpression::Binary(Binary { operator, lhs: Box::new(expr), rhs: Box::new(or(stream)?) }))
+    } else {
+        Ok(expr)
+    }
+}
+
+pub fn or(stream: &mut ParseStream) -> Result<AbstractExpression> {
+    let mut expr = and(stream)?;
+    while let Some(operator) = stream.get(TokenKind::OrOr) {
+        expr = AbstractExpression::Binary(Binary { operator, lhs: Box::new(expr), rhs: Box::new(and(stream)?) });
+    }
+    Ok(expr)
+}
+
+pub fn and(stream: &mut ParseStream) -> Result<AbstractExpression> {
+    let mut expr = equality(stream)?;
+    while let Some(operator) = stream.get(TokenKind::AndAnd) {
+        expr = AbstractExpression::Binary(Binary { operator, lhs: Box::new(expr), rhs: Box::new(equality(stream)?) });
+    }
+    Ok(expr)
+}
+
+pub fn equality(stream: &mut ParseStream) -> Result<AbstractExpression> {
+    let mut expr = comparison(stream)?;
+    while let Some(operator) = stream.get_any([TokenKind::EqEq, TokenKind::BangEq]) {
+        expr = AbstractExpression::Binary(Binary { operator, lhs: Box::new(expr), rhs: Box::new(comparison(stream)?) });
+    }
+    Ok(expr)
+}
+
+pub fn comparison(stream: &mut ParseStream) -> Result<AbstractExpression> {
+    let mut expr = add(stream)?;
+    while let Some(operator) = stream.get_any([TokenKind::Lt, TokenKind::LtEq, TokenKind::Gt, TokenKind::GtEq]) {
+        expr = AbstractExpression::Binary(Binary { operator, lhs: Box::new(expr), rhs: Box::new(add(stream)?) });
+    }
+    Ok(expr)
 }
 
 pub fn add(stream: &mut ParseStream) -> Result<AbstractExpression> {
@@ -41,7 +469,7 @@ pub fn add(stream: &mut ParseStream) -> Result<AbstractExpression> {
 
 pub fn mul(stream: &mut ParseStream) -> Result<AbstractExpression> {
     let mut expr = unary(stream)?;
-    while let Some(operator) = stream.get_any([TokenKind::Star, TokenKind::Slash]) {
+    while let Some(operator) = stream.get_any([TokenKind::Star, TokenKind::Slash, TokenKind::Percent]) {
         expr = AbstractExpression::Binary(Binary { operator, lhs: Box::new(expr), rhs: Box::new(unary(stream)?) });
     }
     Ok(expr)
@@ -49,20 +477,60 @@ pub fn mul(stream: &mut ParseStream) -> Result<AbstractExpression> {
 
 pub fn unary(stream: &mut ParseStream) -> Result<AbstractExpression> {
     if let Some(op) = stream.get_any([TokenKind::Bang, TokenKind::Minus]) {
-        Ok(AbstractExpression::Unary(Unary { op, expr: Box::new(unary(stream)?) }))
+        let expr = unary(stream)?;
+        match expr {
+            AbstractExpression::Literal(literal) if op.kind == TokenKind::Minus => negative_literal(stream, op, literal),
+            expr => Ok(AbstractExpression::Unary(Unary { op, expr: Box::new(expr) })),
+        }
     } else {
         property(stream)
     }
 }
 
+// Folds `-` into an integer literal, which makes it an int. Otherwise `-1` would negate a uint and
+// the smallest int couldn't be written at all. Literals with a `u` suffix stay unsigned.
+fn negative_literal(stream: &mut ParseStream, op: Token, literal: Literal) -> Result<AbstractExpression> {
+    let mut span = op.span;
+    span.extend(&literal.token.span);
+    let value = match literal.value {
+        AbstractLiteral::UInt(value) if !stream.src_from_span(literal.token.span).ends_with('u') => {
+            0i64.checked_sub_unsigned(value).ok_or_else(|| ParseError {
+                span,
+                found: Some(literal.token.kind),
+                expected: vec![],
+                details: String::from("Integer literal is too small for type int."),
+            })?
+        }
+        AbstractLiteral::Int(value) => value.checked_neg().ok_or_else(|| ParseError {
+            span,
+            found: Some(literal.token.kind),
+            expected: vec![],
+            details: String::from("Integer literal is too large for type int."),
+        })?,
+        _ => return Ok(AbstractExpression::Unary(Unary { op, expr: Box::new(AbstractExpression::Literal(literal)) })),
+    };
+    let token = Token { kind: TokenKind::Int, span };
+    Ok(AbstractExpression::Literal(Literal { token, value: AbstractLiteral::Int(value) }))
+}
+
 pub fn property(stream: &mut ParseStream) -> Result<AbstractExpression> {
-    if let Some(init_prop) = stream.get(TokenKind::Ident) {
-        let mut expr = AbstractExpression::PropertyAccess(PropertyAccess { obj: None, property: init_prop });
+    if let Some(init_prop) = stream.get_any(PATH_START) {
+        let mut expr = if stream.peeks(TokenKind::ColonColon) {
+            let mut segments = vec![init_prop];
+            while stream.gets(TokenKind::ColonColon) {
+                segments.push(path_segment(stream)?);
+            }
+            AbstractExpression::Path(Path { segments })
+        } else if init_prop.kind == TokenKind::Ident {
+            AbstractExpression::PropertyAccess(PropertyAccess { obj: None, property: init_prop, resolution: None })
+        } else {
+            return Err(stream.error(&[TokenKind::ColonColon], &format!("Expected '::' after '{}'.", init_prop.kind)));
+        };
         while let Some(tok) = stream.get_any([TokenKind::Dot, TokenKind::LParen]) {
             match tok.kind {
                 TokenKind::Dot => {
                     let property = expect_ident(stream)?;
-                    expr = AbstractExpression::PropertyAccess(PropertyAccess { obj: Some(Box::new(expr)), property });
+                    expr = AbstractExpression::PropertyAccess(PropertyAccess { obj: Some(Box::new(expr)), property, resolution: None });
                 },
                 TokenKind::LParen => {
                     let mut args = vec![];
@@ -91,37 +559,144 @@ pub fn grouping(stream: &mut ParseStream) -> Result<AbstractExpression> {
         let inside = expression(stream)?;
         stream.expect(TokenKind::RParen, "Expected closing parenthesis ')' after expression.")?;
         Ok(AbstractExpression::Grouping(Box::new(inside)))
+    } else {
+        block_expr(stream)
+    }
+}
+
+pub fn block_expr(stream: &mut ParseStream) -> Result<AbstractExpression> {
+    if stream.peeks(TokenKind::LBrace) {
+        // `return` inside a block expression gives the block its value.
+        stream.return_depth += 1;
+        let block = expect_block(stream);
+        stream.return_depth -= 1;
+        Ok(AbstractExpression::BlockExpression(block?))
+    } else if stream.peeks(TokenKind::If) {
+        Ok(AbstractExpression::If(if_else(stream)?))
+    } else {
+        list(stream)
+    }
+}
+
+pub fn list(stream: &mut ParseStream) -> Result<AbstractExpression> {
+    if let Some(bracket) = stream.get(TokenKind::LBracket) {
+        let mut items = vec![];
+        while !stream.peeks(TokenKind::RBracket) {
+            items.push(expression(stream)?);
+            if !stream.gets(TokenKind::Comma) {
+                break;
+            }
+        }
+        stream.expect(TokenKind::RBracket, "Expected a closing bracket ']' after list items.")?;
+        Ok(AbstractExpression::List(List { bracket, items }))
     } else {
         literal(stream)
     }
 }
 
+// Every token kind that can begin an expression.
+const EXPRESSION_START: [TokenKind; 15] = [
+    TokenKind::Bang,
+    TokenKind::Minus,
+    TokenKind::Ident,
+    TokenKind::Super,
+    TokenKind::Crate,
+    TokenKind::LParen,
+    TokenKind::LBrace,
+    TokenKind::If,
+    TokenKind::LBracket,
+    TokenKind::True,
+    TokenKind::False,
+    TokenKind::UInt,
+    TokenKind::Int,
+    TokenKind::Float,
+    TokenKind::String,
+];
+
 pub fn literal(stream: &mut ParseStream) -> Result<AbstractExpression> {
-    let tok = stream.get_any([TokenKind::UInt, TokenKind::String]).ok_or("Expected number or string.")?;
-    Ok(AbstractExpression::Literal(match tok.kind {
-        TokenKind::UInt => AbstractLiteral::UInt(stream.src_from_span(tok.span).parse().unwrap()),
-        TokenKind::String => {
-            // FIXME: This is hella sus
-            let src = stream.src(tok.span.0.index+1..tok.span.1.index-1);
-            AbstractLiteral::String(String::from(src))
-        },
+    let tok = stream
+        .get_any([TokenKind::True, TokenKind::False, TokenKind::UInt, TokenKind::Int, TokenKind::Float, TokenKind::String])
+        .ok_or_else(|| stream.error(&EXPRESSION_START, "Expected an expression."))?;
+    let value = match tok.kind {
+        TokenKind::True => AbstractLiteral::Bool(true),
+        TokenKind::False => AbstractLiteral::Bool(false),
+        TokenKind::UInt | TokenKind::Int | TokenKind::Float => {
+            let value = number_value(stream.src_from_span(tok.span))
+                .map_err(|details| ParseError { span: tok.span, found: Some(tok.kind), expected: vec![], details })?;
+            match value {
+                Number::UInt(value) => AbstractLiteral::UInt(value),
+                Number::Int(value) => AbstractLiteral::Int(value),
+                Number::Float(value) => AbstractLiteral::Float(value),
+            }
+        }
+        TokenKind::String => return string(stream, tok),
         _ => unreachable!(),
-    }))
+    };
+    Ok(AbstractExpression::Literal(Literal { token: tok, value }))
+}
+
+fn string(stream: &mut ParseStream, tok: Token) -> Result<AbstractExpression> {
+    // The lexer has already checked the string, but report any error properly anyway.
+    let lex_error = |error: LexError| ParseError { span: error.span, found: Some(tok.kind), expected: vec![], details: error.details };
+    let segments = string_segments(stream.lexeme, tok.span).map_err(lex_error)?;
+    if let [Segment::Text(text)] = segments.as_slice() {
+        return Ok(AbstractExpression::Literal(Literal { token: tok, value: AbstractLiteral::String(text.clone()) }));
+    }
+
+    let mut parts = vec![];
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => parts.push(FormatPart::Text(text)),
+            Segment::Interpolation(span) => {
+                let tokens = tokenize_span(stream.lexeme, span)
+                    .map_err(|errors| lex_error(errors.into_iter().next().expect("no lex errors")))?;
+                let mut inner = ParseStream::new(tokens, stream.lexeme);
+                let expr = expression(&mut inner).and_then(|expr| match inner.peek() {
+                    Some(_) => Err(inner.error(&[], "Expected '}' after interpolated expression.")),
+                    None => Ok(expr),
+                });
+                for error in inner.take_errors() {
+                    stream.report(error);
+                }
+                parts.push(FormatPart::Expr(expr?));
+            }
+        }
+    }
+    Ok(AbstractExpression::Format(Format { string: tok, parts }))
 }
 
 pub fn expect_ident(stream: &mut ParseStream) -> Result<Token>{
-    stream.get(TokenKind::Ident).ok_or("Expected identifier.")
+    stream
+        .get(TokenKind::Ident)
+        .ok_or_else(|| stream.error(&[TokenKind::Ident], "Expected identifier."))
 }
 
 pub fn expect_block(stream: &mut ParseStream) -> Result<Block> {
     stream.expect(TokenKind::LBrace, "Expected opening brace '{' before block.")?;
 
+    stream.block_depth += 1;
     let mut stmts = vec![];
-    while !stream.peeks(TokenKind::RBrace) {
-        stmts.push(statement(stream)?);
+    let mut value = None;
+    while stream.peek().is_some() && !stream.peeks(TokenKind::RBrace) {
+        let stmt = statement(stream);
+        let terminated = stream.previous().is_some_and(|tok| tok.kind == TokenKind::Semi);
+        match stmt {
+            AbstractStatement::Expr(expr) if !terminated && stream.peeks(TokenKind::RBrace) => {
+                value = Some(Box::new(expr));
+            }
+            // Blocks and `if`s need no semicolon, so one at the end also gives the block its value.
+            AbstractStatement::If(if_) if !terminated && stream.peeks(TokenKind::RBrace) => {
+                value = Some(Box::new(AbstractExpression::If(if_)));
+            }
+            AbstractStatement::Block(block) if !terminated && stream.peeks(TokenKind::RBrace) => {
+                value = Some(Box::new(AbstractExpression::TrailingBlock(block)));
+            }
+            stmt => stmts.push(stmt),
+        }
     }
+    stream.block_depth -= 1;
 
     stream.expect(TokenKind::RBrace, "Expected closing brace '}' after block.")?;
 
-    Ok(Block { stmts })
+    Ok(Block { stmts, value })
 }
\ No newline at end of file
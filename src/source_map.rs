@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use crate::span::{FileId, FileIndex, Span};
+
+/// A loaded source file, with the byte offset of every line start for position lookups.
+#[derive(Debug)]
+pub struct SourceFile {
+    name: String,
+    src: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, src: impl Into<String>) -> SourceFile {
+        let src = src.into();
+        let line_starts = std::iter::once(0).chain(src.match_indices('\n').map(|(index, _)| index + 1)).collect();
+        SourceFile { name: name.into(), src, line_starts }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The text of a zero-based line, without its line break.
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line];
+        let end = self.line_starts.get(line + 1).map_or(self.src.len(), |next| next - 1);
+        let text = &self.src[start..end];
+        text.strip_suffix('\r').unwrap_or(text)
+    }
+
+    /// Looks up the line and columns of a byte offset. The offset must be on a char boundary.
+    pub fn location(&self, index: usize) -> FileIndex {
+        let line = self.line_starts.partition_point(|&start| start <= index) - 1;
+        let before = &self.src[self.line_starts[line]..index];
+        FileIndex {
+            index,
+            line,
+            column: before.chars().count(),
+            utf16_column: before.chars().map(char::len_utf16).sum(),
+        }
+    }
+}
+
+/// Owns every loaded file and hands out the ids that spans refer to them by.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, src: impl Into<String>) -> FileId {
+        let id = FileId(u32::try_from(self.files.len()).expect("too many source files"));
+        self.files.push(SourceFile::new(name, src));
+        id
+    }
+
+    pub fn load(&mut self, path: &Path) -> std::io::Result<FileId> {
+        let src = std::fs::read_to_string(path)?;
+        Ok(self.add(path.display().to_string(), src))
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0 as usize]
+    }
+
+    /// The file with `id`, or `None` if it isn't in this map.
+    pub fn get(&self, id: FileId) -> Option<&SourceFile> {
+        self.files.get(id.0 as usize)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        self.files.iter().enumerate().map(|(id, file)| (FileId(id as u32), file))
+    }
+
+    pub fn src(&self, id: FileId) -> &str {
+        self.file(id).src()
+    }
+
+    pub fn span_str(&self, span: Span) -> &str {
+        &self.src(span.file)[span.range()]
+    }
+
+    pub fn start(&self, span: Span) -> FileIndex {
+        self.file(span.file).location(span.start as usize)
+    }
+
+    pub fn end(&self, span: Span) -> FileIndex {
+        self.file(span.file).location(span.end as usize)
+    }
+}
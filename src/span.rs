@@ -1,67 +1,64 @@
+use std::ops::Range;
+
+/// Identifies a file loaded into a [`SourceMap`](crate::source_map::SourceMap).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(pub u32);
+
+/// A position in a file, as looked up from a byte offset by the source map.
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub struct FileIndex {
+    /// Byte offset into the source.
     pub index: usize,
     pub line: usize,
+    /// Column in chars, for diagnostics.
     pub column: usize,
+    /// Column in UTF-16 code units, for editors.
+    pub utf16_column: usize,
 }
 
-#[derive(Copy, Debug, Default, Clone, PartialEq, Eq)]
-pub struct Span(pub FileIndex, pub FileIndex);
+/// A byte range in one file.
+#[derive(Copy, Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file: FileId,
+    pub start: u32,
+    pub end: u32,
+}
 
 impl Span {
+    pub fn new(file: FileId, start: usize, end: usize) -> Span {
+        Span { file, start: offset(start), end: offset(end) }
+    }
+
+    pub fn range(self) -> Range<usize> {
+        self.start as usize..self.end as usize
+    }
+
+    pub fn len(self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+
+    /// The empty span just after this one.
+    pub fn after(self) -> Span {
+        Span { start: self.end, ..self }
+    }
+
     pub fn notice(&mut self, c: char) {
-        self.1.index += 1;
-        self.1.column += 1;
-        if c == '\n' {
-            self.1.line += 1;
-            self.1.column = 0;
-        }
+        self.end += c.len_utf8() as u32;
     }
 
     pub fn extend(&mut self, other: &Span) {
-        self.1 = other.1;
+        self.end = other.end;
     }
 
     pub fn blip(&mut self) {
-        self.0 = self.1;
+        self.start = self.end;
     }
-
-    /*pub fn display<'a>(self, src: &'a str) -> DisplaySpan<'a> {
-        DisplaySpan { span: self, src }
-    }*/
 }
 
-// FIXME: Move this to a new ParseError.
-
-/*#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct DisplaySpan<'a> {
-    span: Span,
-    src: &'a str,
+fn offset(index: usize) -> u32 {
+    u32::try_from(index).expect("source files are limited to 4 GiB")
 }
-
-impl<'a> Display for DisplaySpan<'a> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // TODO: Add support for multiple lines.
-        assert_eq!(self.span.0.line, self.span.1.line);
-        let line = self
-            .src
-            .lines()
-            .nth(self.span.0.line)
-            .unwrap_or_else(|| panic!("Line number {} doesn't exist.", self.span.0.line));
-        let line_number_prefix = format!("{} | ", self.span.0.line + 1);
-        let arrows = format!(
-            "{}{}",
-            " ".repeat(self.span.0.column + line_number_prefix.len()),
-            "^".repeat(self.span.1.column - self.span.0.column)
-        );
-        write!(
-            f,
-            "[{}:{}]\n{}{}\n{}",
-            self.span.0.line + 1,
-            self.span.0.line + 1,
-            line_number_prefix,
-            line,
-            arrows
-        )
-    }
-}*/
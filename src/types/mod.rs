@@ -0,0 +1,590 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::lexer::token::{Token, TokenKind};
+use crate::modules::{ModuleGraph, ModuleId};
+use crate::parser::ast::*;
+use crate::source_map::SourceMap;
+use crate::span::Span;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub span: Span,
+    pub details: String,
+    // The declarations involved, like the annotation a value doesn't match.
+    pub related: Vec<(Span, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Unit,
+    Bool,
+    UInt,
+    Int,
+    Float,
+    String,
+    Range,
+    List(Box<Type>),
+    // No parameters means any number of arguments of any type, like the natives take.
+    Function { params: Option<Vec<Type>>, returns: Box<Type> },
+    // Not known until the program runs, so any type is accepted.
+    Unknown,
+}
+
+impl Type {
+    /// Whether a value of type `found` can be used where `self` is expected.
+    pub fn accepts(&self, found: &Type) -> bool {
+        match (self, found) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (Type::List(expected), Type::List(found)) => expected.accepts(found),
+            (Type::Function { params: expected, returns: expected_returns }, Type::Function { params: found, returns: found_returns }) => {
+                let params = match (expected, found) {
+                    (Some(expected), Some(found)) => {
+                        expected.len() == found.len() && expected.iter().zip(found).all(|(expected, found)| found.accepts(expected))
+                    }
+                    _ => true,
+                };
+                params && expected_returns.accepts(found_returns)
+            }
+            (expected, found) => expected == found,
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        *self != Type::Unknown
+    }
+
+    // The type both branches of an `if` or items of a list have, if they agree.
+    fn join(self, other: Type) -> Type {
+        if self == other {
+            self
+        } else {
+            Type::Unknown
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Unit => write!(f, "unit"),
+            Type::Bool => write!(f, "bool"),
+            Type::UInt => write!(f, "uint"),
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Range => write!(f, "range"),
+            Type::List(item) => write!(f, "[{}]", item),
+            Type::Function { params: Some(params), returns } => {
+                let params: Vec<String> = params.iter().map(Type::to_string).collect();
+                write!(f, "fun({}) -> {}", params.join(", "), returns)
+            }
+            Type::Function { params: None, returns } => write!(f, "fun(..) -> {}", returns),
+            Type::Unknown => write!(f, "_"),
+        }
+    }
+}
+
+// A variable's type, with where it was declared.
+#[derive(Debug, Clone)]
+struct Variable {
+    ty: Type,
+    span: Span,
+}
+
+// The function being checked.
+#[derive(Default)]
+struct FunctionTypes {
+    // The type of each local, by the index the resolver gave it.
+    locals: Vec<Option<Variable>>,
+    // The declared return type and its annotation.
+    returns: Option<(Type, Span)>,
+    // One entry per block expression being checked, recording whether a `return` exits it.
+    blocks: Vec<bool>,
+}
+
+/// Checks the types of every module in `graph`. Values without an annotation get the type they
+/// are initialised with, and anything the checker can't work out is left to be checked at runtime.
+/// `modules::load` doesn't call this, so programs are only checked by callers that opt in.
+pub fn check(graph: &ModuleGraph, sources: &SourceMap) -> Vec<TypeError> {
+    let mut checker = Checker { graph, sources, module: graph.root(), globals: HashMap::new(), functions: Vec::new(), errors: Vec::new() };
+    for (id, module) in graph.modules() {
+        checker.module = id;
+        checker.declare_globals(&module.ast);
+    }
+
+    // Top-level code runs first, so function bodies see the types of every global.
+    let mut bodies = vec![];
+    for &id in graph.order() {
+        checker.module = id;
+        checker.functions.push(FunctionTypes::default());
+        for stmt in &graph.module(id).ast {
+            match stmt {
+                AbstractStatement::FunctionDecl(decl) => bodies.push((id, decl)),
+                stmt => checker.statement(stmt),
+            }
+        }
+        checker.functions.pop();
+    }
+    for (id, decl) in bodies {
+        checker.module = id;
+        let Some(Variable { ty: Type::Function { params: Some(params), returns }, .. }) = checker.globals.get(&checker.qualify(decl.ident)).cloned()
+        else {
+            continue;
+        };
+        checker.function_body(decl, params, *returns);
+    }
+    checker.errors
+}
+
+struct Checker<'a> {
+    graph: &'a ModuleGraph,
+    sources: &'a SourceMap,
+    module: ModuleId,
+    // Top-level declarations by qualified name.
+    globals: HashMap<String, Variable>,
+    // The innermost function being checked is last. Each module's top-level code counts as one.
+    functions: Vec<FunctionTypes>,
+    errors: Vec<TypeError>,
+}
+
+impl Checker<'_> {
+    fn declare_globals(&mut self, ast: &Ast) {
+        for stmt in ast {
+            let (ident, ty) = match stmt {
+                AbstractStatement::FunctionDecl(decl) => (decl.ident, self.signature(decl)),
+                // Unannotated globals get the type of their value once it has been checked.
+                AbstractStatement::Let(let_) => (let_.ident, let_.ty.as_ref().map_or(Type::Unknown, |ty| self.annotation(ty))),
+                _ => continue,
+            };
+            let name = self.qualify(ident);
+            self.globals.insert(name, Variable { ty, span: ident.span });
+        }
+    }
+
+    fn signature(&mut self, decl: &FunctionDecl) -> Type {
+        let params = decl.arguments.iter().map(|arg| arg.ty.as_ref().map_or(Type::Unknown, |ty| self.annotation(ty))).collect();
+        let returns = decl.returns.as_ref().map_or(Type::Unknown, |ty| self.annotation(ty));
+        Type::Function { params: Some(params), returns: Box::new(returns) }
+    }
+
+    fn annotation(&mut self, ty: &TypeExpr) -> Type {
+        match &ty.kind {
+            TypeExprKind::Named(name) => match self.sources.span_str(name.span) {
+                "unit" => Type::Unit,
+                "bool" => Type::Bool,
+                "uint" => Type::UInt,
+                "int" => Type::Int,
+                "float" => Type::Float,
+                "string" => Type::String,
+                "range" => Type::Range,
+                "list" => Type::List(Box::new(Type::Unknown)),
+                other => {
+                    self.error(name.span, format!("Unknown type `{}`.", other));
+                    Type::Unknown
+                }
+            },
+            TypeExprKind::List(item) => Type::List(Box::new(self.annotation(item))),
+            TypeExprKind::Function { params, returns } => Type::Function {
+                params: Some(params.iter().map(|param| self.annotation(param)).collect()),
+                returns: Box::new(returns.as_ref().map_or(Type::Unit, |returns| self.annotation(returns))),
+            },
+        }
+    }
+
+    fn statement(&mut self, stmt: &AbstractStatement) {
+        match stmt {
+            AbstractStatement::Expr(expr) => {
+                self.expression(expr);
+            }
+            AbstractStatement::Block(block) => {
+                self.block(block);
+            }
+            AbstractStatement::FunctionDecl(decl) => {
+                // Nested functions are locals, declared before the body so they can call themselves.
+                let Type::Function { params: Some(params), returns } = self.signature(decl) else {
+                    unreachable!("signature isn't a function");
+                };
+                let ty = Type::Function { params: Some(params.clone()), returns: returns.clone() };
+                self.declare(decl.ident, decl.local, ty);
+                self.function_body(decl, params, *returns);
+            }
+            AbstractStatement::Let(let_) => {
+                let found = self.expression(&let_.value);
+                let ty = match &let_.ty {
+                    // Top-level annotations were checked when declaring the globals.
+                    Some(_) if let_.local.is_none() => self.globals[&self.qualify(let_.ident)].ty.clone(),
+                    Some(annotation) => self.annotation(annotation),
+                    // `()` usually means there's no value yet, so it doesn't fix the variable's type.
+                    None if found == Type::Unit => Type::Unknown,
+                    None => found.clone(),
+                };
+                if let Some(annotation) = &let_.ty {
+                    let span = let_.value.span().unwrap_or(let_.ident.span);
+                    self.expect(&ty, &found, span, annotation.span, "expected because of this annotation");
+                }
+                self.declare(let_.ident, let_.local, ty);
+            }
+            AbstractStatement::Return(ret) => {
+                let found = ret.value.as_ref().map_or(Type::Unit, |value| self.expression(value));
+                let function = self.function();
+                if let Some(exits) = function.blocks.last_mut() {
+                    *exits = true;
+                } else if let Some((expected, annotation)) = function.returns.clone() {
+                    let span = ret.value.as_ref().and_then(AbstractExpression::span).unwrap_or(ret.keyword.span);
+                    self.expect(&expected, &found, span, annotation, "return type declared here");
+                }
+            }
+            AbstractStatement::If(if_) => {
+                self.if_else(if_);
+            }
+            AbstractStatement::While(while_) => {
+                self.expression(&while_.condition);
+                self.block(&while_.body);
+            }
+            AbstractStatement::For(for_) => {
+                let item = match self.expression(&for_.iterable) {
+                    Type::Range => Type::UInt,
+                    Type::List(item) => *item,
+                    Type::Unknown => Type::Unknown,
+                    other => {
+                        let span = for_.iterable.span().unwrap_or(for_.keyword.span);
+                        self.error(span, format!("Cannot iterate over a value of type {}.", other));
+                        Type::Unknown
+                    }
+                };
+                self.declare(for_.binding, for_.local, item);
+                self.block(&for_.body);
+            }
+            AbstractStatement::Break(_)
+            | AbstractStatement::Continue(_)
+            | AbstractStatement::Module(_)
+            | AbstractStatement::Use(_)
+            | AbstractStatement::Error(_) => (),
+        }
+    }
+
+    fn function_body(&mut self, decl: &FunctionDecl, params: Vec<Type>, returns: Type) {
+        let mut function = FunctionTypes::default();
+        for (arg, ty) in decl.arguments.iter().zip(params) {
+            function.locals.push(Some(Variable { ty, span: arg.ident.span }));
+        }
+        function.returns = decl.returns.as_ref().map(|annotation| (returns, annotation.span));
+        self.functions.push(function);
+        for stmt in &decl.body.stmts {
+            self.statement(stmt);
+        }
+        let found = decl.body.value.as_ref().map(|value| (self.expression(value), value.span()));
+        // Every `return` has already been checked, so only falling off the end of the body is left.
+        if let Some((expected, annotation)) = self.function().returns.clone().filter(|_| !always_returns(&decl.body)) {
+            let (found, span) = found.unwrap_or((Type::Unit, None));
+            self.expect(&expected, &found, span.unwrap_or(decl.ident.span), annotation, "return type declared here");
+        }
+        self.functions.pop();
+    }
+
+    fn block(&mut self, block: &Block) -> Type {
+        for stmt in &block.stmts {
+            self.statement(stmt);
+        }
+        match &block.value {
+            Some(value) => self.expression(value),
+            None => Type::Unit,
+        }
+    }
+
+    fn if_else(&mut self, if_: &If) -> Type {
+        self.expression(&if_.condition);
+        let then = self.block(&if_.then);
+        let otherwise = match &if_.otherwise {
+            Some(Else::If(nested)) => self.if_else(nested),
+            Some(Else::Block(block)) => self.block(block),
+            None => Type::Unit,
+        };
+        then.join(otherwise)
+    }
+
+    fn expression(&mut self, expr: &AbstractExpression) -> Type {
+        match expr {
+            AbstractExpression::Grouping(inner) => self.expression(inner),
+            AbstractExpression::Binary(binary) => {
+                let lhs = self.expression(&binary.lhs);
+                let rhs = self.expression(&binary.rhs);
+                self.binary(binary.operator, lhs, rhs)
+            }
+            AbstractExpression::Literal(literal) => match literal.value {
+                AbstractLiteral::Bool(_) => Type::Bool,
+                AbstractLiteral::UInt(_) => Type::UInt,
+                AbstractLiteral::Int(_) => Type::Int,
+                AbstractLiteral::Float(_) => Type::Float,
+                AbstractLiteral::String(_) => Type::String,
+            },
+            AbstractExpression::BlockExpression(block) => {
+                self.function().blocks.push(false);
+                let ty = self.block(block);
+                // A `return` gives the block a value of its own.
+                match self.function().blocks.pop() {
+                    Some(true) => Type::Unknown,
+                    _ => ty,
+                }
+            }
+            AbstractExpression::TrailingBlock(block) => self.block(block),
+            AbstractExpression::PropertyAccess(access) => match &access.obj {
+                Some(obj) => {
+                    let ty = self.expression(obj);
+                    self.no_property(&ty, access.property);
+                    Type::Unknown
+                }
+                None => self.variable(access).map_or(Type::Unknown, |variable| variable.ty),
+            },
+            AbstractExpression::Unary(unary) => {
+                let ty = self.expression(&unary.expr);
+                match (unary.op.kind, ty) {
+                    (TokenKind::Bang, _) => Type::Bool,
+                    (_, ty @ (Type::Int | Type::Float | Type::Unknown)) => ty,
+                    (_, ty) => {
+                        self.error(unary.op.span, format!("Cannot apply unary '-' to a value of type {}.", ty));
+                        Type::Unknown
+                    }
+                }
+            }
+            AbstractExpression::Call(call) => self.call(call),
+            AbstractExpression::If(if_) => self.if_else(if_),
+            AbstractExpression::List(list) => {
+                let items: Vec<Type> = list.items.iter().map(|item| self.expression(item)).collect();
+                let item = items.into_iter().reduce(Type::join).unwrap_or(Type::Unknown);
+                Type::List(Box::new(item))
+            }
+            AbstractExpression::Assign(assign) => self.assign(assign),
+            AbstractExpression::Format(format) => {
+                for part in &format.parts {
+                    if let FormatPart::Expr(expr) = part {
+                        self.expression(expr);
+                    }
+                }
+                Type::String
+            }
+            AbstractExpression::Path(path) => {
+                let global = self.graph.path(path).and_then(|qualified| self.globals.get(qualified));
+                global.map_or(Type::Unknown, |global| global.ty.clone())
+            }
+        }
+    }
+
+    fn binary(&mut self, operator: Token, lhs: Type, rhs: Type) -> Type {
+        let numeric = |ty: &Type| matches!(ty, Type::UInt | Type::Int | Type::Float);
+        let integer = |ty: &Type| matches!(ty, Type::UInt | Type::Int);
+        let valid = match operator.kind {
+            // Both of these give back one of their operands, not a bool.
+            TokenKind::AndAnd | TokenKind::OrOr => return lhs.join(rhs),
+            TokenKind::EqEq | TokenKind::BangEq => return Type::Bool,
+            _ if !lhs.is_known() || !rhs.is_known() => {
+                return match operator.kind {
+                    TokenKind::Lt | TokenKind::LtEq | TokenKind::Gt | TokenKind::GtEq => Type::Bool,
+                    TokenKind::DotDot => Type::Range,
+                    _ => Type::Unknown,
+                };
+            }
+            TokenKind::Plus | TokenKind::Minus if integer(&lhs) && integer(&rhs) => true,
+            TokenKind::Plus => lhs == rhs && (numeric(&lhs) || lhs == Type::String),
+            TokenKind::Minus | TokenKind::Star | TokenKind::Slash | TokenKind::Percent => lhs == rhs && numeric(&lhs),
+            TokenKind::Lt | TokenKind::LtEq | TokenKind::Gt | TokenKind::GtEq => lhs == rhs && (numeric(&lhs) || lhs == Type::String),
+            TokenKind::DotDot => lhs == Type::UInt && rhs == Type::UInt,
+            _ => true,
+        };
+        if !valid {
+            let operator_text = self.sources.span_str(operator.span);
+            self.error(operator.span, format!("Cannot apply '{}' to values of type {} and {}.", operator_text, lhs, rhs));
+            return Type::Unknown;
+        }
+        match operator.kind {
+            TokenKind::Lt | TokenKind::LtEq | TokenKind::Gt | TokenKind::GtEq => Type::Bool,
+            TokenKind::DotDot => Type::Range,
+            _ => lhs,
+        }
+    }
+
+    fn call(&mut self, call: &Call) -> Type {
+        let callee = self.expression(&call.expr);
+        let args: Vec<Type> = call.args.iter().map(|arg| self.expression(arg)).collect();
+        let span = call.expr.span().unwrap_or_default();
+        let (params, returns) = match callee {
+            Type::Function { params, returns } => (params, *returns),
+            Type::Unknown => return Type::Unknown,
+            other => {
+                self.error(span, format!("Cannot call a value of type {}.", other));
+                return Type::Unknown;
+            }
+        };
+        let Some(params) = params else {
+            return returns;
+        };
+        let declaration = self.declaration(&call.expr);
+        let related = |message: &str| declaration.iter().map(|(_, span)| (*span, String::from(message))).collect();
+        if params.len() != args.len() {
+            let name = declaration.as_ref().map_or_else(|| String::from("function"), |(name, _)| format!("`{}`", name));
+            self.errors.push(TypeError {
+                span,
+                details: format!("Function {} takes {} arguments but {} were given.", name, params.len(), args.len()),
+                related: related("declared here"),
+            });
+            return returns;
+        }
+        for (i, ((param, found), arg)) in params.iter().zip(&args).zip(&call.args).enumerate() {
+            if !param.accepts(found) {
+                self.errors.push(TypeError {
+                    span: arg.span().unwrap_or(span),
+                    details: format!("Expected a value of type {} for argument {}, but found {}.", param, i + 1, found),
+                    related: related("parameter types declared here"),
+                });
+            }
+        }
+        returns
+    }
+
+    fn assign(&mut self, assign: &Assign) -> Type {
+        let target = &assign.target;
+        let found = self.expression(&assign.value);
+        if let Some(obj) = &target.obj {
+            let ty = self.expression(obj);
+            self.no_property(&ty, target.property);
+            return found;
+        }
+        let Some(variable) = self.variable(target) else {
+            return found;
+        };
+        let found = match assign.operator.kind {
+            TokenKind::PlusEq => self.binary(Token { kind: TokenKind::Plus, ..assign.operator }, variable.ty.clone(), found),
+            TokenKind::MinusEq => self.binary(Token { kind: TokenKind::Minus, ..assign.operator }, variable.ty.clone(), found),
+            TokenKind::StarEq => self.binary(Token { kind: TokenKind::Star, ..assign.operator }, variable.ty.clone(), found),
+            TokenKind::SlashEq => self.binary(Token { kind: TokenKind::Slash, ..assign.operator }, variable.ty.clone(), found),
+            _ => found,
+        };
+        if !variable.ty.accepts(&found) {
+            let name = self.sources.span_str(target.property.span);
+            self.errors.push(TypeError {
+                span: assign.value.span().unwrap_or(assign.operator.span),
+                details: format!("Cannot assign a value of type {} to `{}`, which has type {}.", found, name, variable.ty),
+                related: vec![(variable.span, String::from("declared here"))],
+            });
+        }
+        found
+    }
+
+    fn no_property(&mut self, ty: &Type, property: Token) {
+        if ty.is_known() {
+            let name = self.sources.span_str(property.span);
+            self.error(property.span, format!("Value of type {} has no property `{}`.", ty, name));
+        }
+    }
+
+    // The variable a name in the current environment refers to, if its type is known.
+    fn variable(&mut self, access: &PropertyAccess) -> Option<Variable> {
+        match access.resolution.as_ref()? {
+            Resolution::Local(index) => self.function().locals.get(*index).cloned().flatten(),
+            Resolution::Global(qualified) => self.globals.get(qualified).cloned(),
+            Resolution::Builtin => {
+                let ty = Type::Function { params: None, returns: Box::new(Type::Unit) };
+                Some(Variable { ty, span: access.property.span })
+            }
+            // Locals of enclosing functions aren't tracked.
+            Resolution::Capture(_) => None,
+        }
+    }
+
+    // The name and declaration of the function a call refers to, for pointing at its signature.
+    fn declaration(&mut self, callee: &AbstractExpression) -> Option<(String, Span)> {
+        match callee {
+            AbstractExpression::PropertyAccess(access @ PropertyAccess { obj: None, .. }) => {
+                if access.resolution == Some(Resolution::Builtin) {
+                    return None;
+                }
+                let variable = self.variable(access)?;
+                Some((String::from(self.sources.span_str(access.property.span)), variable.span))
+            }
+            AbstractExpression::Path(path) => {
+                let qualified = self.graph.path(path)?;
+                Some((String::from(qualified), self.globals.get(qualified)?.span))
+            }
+            AbstractExpression::Grouping(inner) => self.declaration(inner),
+            _ => None,
+        }
+    }
+
+    fn declare(&mut self, ident: Token, local: Option<usize>, ty: Type) {
+        let variable = Variable { ty, span: ident.span };
+        match local {
+            Some(index) => {
+                let locals = &mut self.function().locals;
+                if locals.len() <= index {
+                    locals.resize(index + 1, None);
+                }
+                locals[index] = Some(variable);
+            }
+            None => {
+                let name = self.qualify(ident);
+                let global = self.globals.get_mut(&name).expect("undeclared global");
+                // Annotated globals keep their annotation.
+                if !global.ty.is_known() {
+                    global.ty = variable.ty;
+                }
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: &Type, found: &Type, span: Span, declared: Span, reason: &str) {
+        if !expected.accepts(found) {
+            self.errors.push(TypeError {
+                span,
+                details: format!("Expected a value of type {}, but found {}.", expected, found),
+                related: vec![(declared, String::from(reason))],
+            });
+        }
+    }
+
+    fn qualify(&self, ident: Token) -> String {
+        self.graph.module(self.module).qualify(self.sources.span_str(ident.span))
+    }
+
+    fn function(&mut self) -> &mut FunctionTypes {
+        self.functions.last_mut().expect("no function being checked")
+    }
+
+    fn error(&mut self, span: Span, details: String) {
+        self.errors.push(TypeError { span, details, related: Vec::new() });
+    }
+}
+
+// Whether running `block` always ends in a `return` from the enclosing function.
+fn always_returns(block: &Block) -> bool {
+    block.stmts.iter().any(statement_returns) || block.value.as_deref().is_some_and(expression_returns)
+}
+
+fn statement_returns(stmt: &AbstractStatement) -> bool {
+    match stmt {
+        AbstractStatement::Return(_) => true,
+        AbstractStatement::Block(block) => always_returns(block),
+        AbstractStatement::If(if_) => if_returns(if_),
+        AbstractStatement::Expr(expr) => expression_returns(expr),
+        _ => false,
+    }
+}
+
+fn expression_returns(expr: &AbstractExpression) -> bool {
+    match expr {
+        AbstractExpression::Grouping(inner) => expression_returns(inner),
+        AbstractExpression::If(if_) => if_returns(if_),
+        AbstractExpression::TrailingBlock(block) => always_returns(block),
+        // A `return` in a block expression only leaves the block.
+        _ => false,
+    }
+}
+
+fn if_returns(if_: &If) -> bool {
+    always_returns(&if_.then)
+        && match &if_.otherwise {
+            Some(Else::If(nested)) => if_returns(nested),
+            Some(Else::Block(block)) => always_returns(block),
+            None => false,
+        }
+}
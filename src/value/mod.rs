@@ -0,0 +1,220 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::rc::Rc;
+
+mod native;
+
+pub use native::Native;
+
+/// A function the backend running a program knows how to call. The interpreter and the VM each
+/// represent functions their own way, but share every other kind of value.
+pub trait Callable {
+    fn name(&self) -> &str;
+}
+
+#[derive(Debug)]
+pub enum Value<F> {
+    Unit,
+    Bool(bool),
+    UInt(u64),
+    Int(i64),
+    Float(f64),
+    String(Rc<str>),
+    // A half-open range of unsigned integers.
+    Range(u64, u64),
+    List(Rc<Vec<Value<F>>>),
+    Function(Rc<F>),
+    Native(Native),
+}
+
+// Not derived, since that would require `F: Clone` although functions are behind an `Rc`.
+impl<F> Clone for Value<F> {
+    fn clone(&self) -> Self {
+        match self {
+            Value::Unit => Value::Unit,
+            Value::Bool(value) => Value::Bool(*value),
+            Value::UInt(value) => Value::UInt(*value),
+            Value::Int(value) => Value::Int(*value),
+            Value::Float(value) => Value::Float(*value),
+            Value::String(value) => Value::String(Rc::clone(value)),
+            Value::Range(start, end) => Value::Range(*start, *end),
+            Value::List(items) => Value::List(Rc::clone(items)),
+            Value::Function(function) => Value::Function(Rc::clone(function)),
+            Value::Native(native) => Value::Native(*native),
+        }
+    }
+}
+
+impl<F> PartialEq for Value<F> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Unit, Value::Unit) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::UInt(a), Value::UInt(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Range(a, b), Value::Range(c, d)) => a == c && b == d,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl<F> Value<F> {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Unit => "unit",
+            Value::Bool(_) => "bool",
+            Value::UInt(_) => "uint",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Range(..) => "range",
+            Value::List(_) => "list",
+            Value::Function(_) | Value::Native(_) => "function",
+        }
+    }
+
+    /// Whether a condition holds. `false`, `()` and zero are false and every other value is true.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            Value::Unit => false,
+            Value::UInt(value) => *value != 0,
+            Value::Int(value) => *value != 0,
+            Value::Float(value) => *value != 0.0,
+            _ => true,
+        }
+    }
+
+    /// The `index`th item of an iterable value, or None once it is exhausted.
+    pub fn iterate(&self, index: u64) -> Result<Option<Value<F>>, String> {
+        match self {
+            Value::Range(start, end) => Ok(start.checked_add(index).filter(|value| value < end).map(Value::UInt)),
+            Value::List(items) => Ok(usize::try_from(index).ok().and_then(|index| items.get(index)).cloned()),
+            other => Err(format!("Cannot iterate over a value of type {}.", other.type_name())),
+        }
+    }
+}
+
+impl<F: Callable> Display for Value<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::UInt(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            // Debug formatting keeps the fraction, so `1.0` doesn't print like the uint `1`.
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Range(start, end) => write!(f, "{}..{}", start, end),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Function(function) => write!(f, "<fun {}>", function.name()),
+            Value::Native(native) => write!(f, "<native fun {}>", native.name()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Range,
+}
+
+impl BinaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Range => "..",
+        }
+    }
+}
+
+/// Applies a binary operator, returning the runtime error message if the operands don't support it.
+pub fn binary<F>(operator: BinaryOp, lhs: Value<F>, rhs: Value<F>) -> Result<Value<F>, String> {
+    // None if the values can't be compared at all. Floats are only partially ordered.
+    let ordering = match (&lhs, &rhs) {
+        (Value::UInt(a), Value::UInt(b)) => Some(a.partial_cmp(b)),
+        (Value::Int(a), Value::Int(b)) => Some(a.partial_cmp(b)),
+        (Value::Float(a), Value::Float(b)) => Some(a.partial_cmp(b)),
+        (Value::String(a), Value::String(b)) => Some(a.partial_cmp(b)),
+        _ => None,
+    };
+    let truth = |holds: bool| Ok(Value::Bool(holds));
+    // Mixed uint and int arithmetic keeps the type of the left operand, so `n + -1` stays a uint.
+    let uint = |value: i128| {
+        u64::try_from(value).map(Value::UInt).map_err(|_| String::from(if value < 0 { "Integer underflow." } else { "Integer overflow." }))
+    };
+    let int = |value: i128| i64::try_from(value).map(Value::Int).map_err(|_| String::from("Integer overflow."));
+    match (operator, &lhs, &rhs) {
+        (BinaryOp::Equal, _, _) => truth(lhs == rhs),
+        (BinaryOp::NotEqual, _, _) => truth(lhs != rhs),
+        (BinaryOp::Less, ..) if ordering.is_some() => truth(ordering.unwrap().is_some_and(Ordering::is_lt)),
+        (BinaryOp::LessEqual, ..) if ordering.is_some() => truth(ordering.unwrap().is_some_and(Ordering::is_le)),
+        (BinaryOp::Greater, ..) if ordering.is_some() => truth(ordering.unwrap().is_some_and(Ordering::is_gt)),
+        (BinaryOp::GreaterEqual, ..) if ordering.is_some() => truth(ordering.unwrap().is_some_and(Ordering::is_ge)),
+        (BinaryOp::Add, Value::UInt(a), Value::UInt(b)) => a.checked_add(*b).map(Value::UInt).ok_or_else(|| String::from("Integer overflow.")),
+        (BinaryOp::Sub, Value::UInt(a), Value::UInt(b)) => a.checked_sub(*b).map(Value::UInt).ok_or_else(|| String::from("Integer underflow.")),
+        (BinaryOp::Mul, Value::UInt(a), Value::UInt(b)) => a.checked_mul(*b).map(Value::UInt).ok_or_else(|| String::from("Integer overflow.")),
+        (BinaryOp::Div, Value::UInt(a), Value::UInt(b)) => a.checked_div(*b).map(Value::UInt).ok_or_else(|| String::from("Division by zero.")),
+        (BinaryOp::Mod, Value::UInt(a), Value::UInt(b)) => a.checked_rem(*b).map(Value::UInt).ok_or_else(|| String::from("Division by zero.")),
+        (BinaryOp::Div | BinaryOp::Mod, Value::Int(_), Value::Int(0)) => Err(String::from("Division by zero.")),
+        (BinaryOp::Add, Value::Int(a), Value::Int(b)) => a.checked_add(*b).map(Value::Int).ok_or_else(|| String::from("Integer overflow.")),
+        (BinaryOp::Sub, Value::Int(a), Value::Int(b)) => a.checked_sub(*b).map(Value::Int).ok_or_else(|| String::from("Integer overflow.")),
+        (BinaryOp::Mul, Value::Int(a), Value::Int(b)) => a.checked_mul(*b).map(Value::Int).ok_or_else(|| String::from("Integer overflow.")),
+        (BinaryOp::Div, Value::Int(a), Value::Int(b)) => a.checked_div(*b).map(Value::Int).ok_or_else(|| String::from("Integer overflow.")),
+        (BinaryOp::Mod, Value::Int(a), Value::Int(b)) => a.checked_rem(*b).map(Value::Int).ok_or_else(|| String::from("Integer overflow.")),
+        (BinaryOp::Add, Value::UInt(a), Value::Int(b)) => uint(i128::from(*a) + i128::from(*b)),
+        (BinaryOp::Sub, Value::UInt(a), Value::Int(b)) => uint(i128::from(*a) - i128::from(*b)),
+        (BinaryOp::Add, Value::Int(a), Value::UInt(b)) => int(i128::from(*a) + i128::from(*b)),
+        (BinaryOp::Sub, Value::Int(a), Value::UInt(b)) => int(i128::from(*a) - i128::from(*b)),
+        (BinaryOp::Add, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
+        (BinaryOp::Sub, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
+        (BinaryOp::Mul, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
+        (BinaryOp::Div, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
+        (BinaryOp::Mod, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a % b)),
+        (BinaryOp::Add, Value::String(a), Value::String(b)) => Ok(Value::String(Rc::from(format!("{}{}", a, b)))),
+        (BinaryOp::Range, Value::UInt(a), Value::UInt(b)) => Ok(Value::Range(*a, *b)),
+        _ => Err(format!(
+            "Cannot apply '{}' to values of type {} and {}.",
+            operator.symbol(),
+            lhs.type_name(),
+            rhs.type_name()
+        )),
+    }
+}
+
+/// Applies unary `-`.
+pub fn negate<F>(value: Value<F>) -> Result<Value<F>, String> {
+    match value {
+        Value::Int(value) => value.checked_neg().map(Value::Int).ok_or_else(|| String::from("Integer overflow.")),
+        Value::Float(value) => Ok(Value::Float(-value)),
+        other => Err(format!("Cannot apply unary '-' to a value of type {}.", other.type_name())),
+    }
+}
@@ -0,0 +1,75 @@
+use std::io::Write;
+
+use super::{Callable, Value};
+
+/// A function built into both backends.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Native {
+    Print,
+    Printf,
+}
+
+impl Native {
+    pub const ALL: [Native; 2] = [Native::Print, Native::Printf];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Native::Print => "print",
+            Native::Printf => "printf",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Native> {
+        Native::ALL.into_iter().find(|native| native.name() == name)
+    }
+
+    /// Runs the native with `args`, writing anything it prints to `out`. Returns the runtime error
+    /// message if it fails.
+    pub fn call<F: Callable>(self, args: &[Value<F>], out: &mut dyn Write) -> Result<Value<F>, String> {
+        let line = match self {
+            Native::Print => {
+                let args: Vec<String> = args.iter().map(Value::to_string).collect();
+                args.join(" ")
+            }
+            Native::Printf => {
+                let Some((Value::String(template), args)) = args.split_first() else {
+                    return Err(String::from("`printf` expects a format string as its first argument."));
+                };
+                let args: Vec<String> = args.iter().map(Value::to_string).collect();
+                format_template(template, &args)?
+            }
+        };
+        writeln!(out, "{}", line).map_err(|error| format!("Failed to print: {}.", error))?;
+        Ok(Value::Unit)
+    }
+}
+
+/// Replaces every `{N}` in `template` with the `N`th argument. Other braces are left as they are,
+/// and every argument has to be used.
+fn format_template(template: &str, args: &[String]) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut used = vec![false; args.len()];
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open + 1..];
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 || !rest[digits..].starts_with('}') {
+            out.push('{');
+            continue;
+        }
+        // Too many digits to be an index is the same as an index past the end.
+        let index = rest[..digits].parse().unwrap_or(usize::MAX);
+        let arg = args
+            .get(index)
+            .ok_or_else(|| format!("Format string refers to argument {} but only {} were given.", &rest[..digits], args.len()))?;
+        out.push_str(arg);
+        used[index] = true;
+        rest = &rest[digits + 1..];
+    }
+    out.push_str(rest);
+    match used.iter().position(|used| !used) {
+        Some(index) => Err(format!("Format string never uses argument {}.", index)),
+        None => Ok(out),
+    }
+}
@@ -0,0 +1,489 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::bytecode::{op, ByteStream, Constant, Function, Program};
+use crate::span::Span;
+use crate::value::{self, BinaryOp, Callable, Native};
+
+const MAX_FRAMES: usize = 1024;
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub span: Span,
+    pub details: String,
+}
+
+pub type Value = value::Value<Closure>;
+
+/// A function along with the locals it captured from the functions it was declared in.
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Rc<Function>,
+    captures: Vec<Rc<RefCell<Capture>>>,
+}
+
+impl Callable for Closure {
+    fn name(&self) -> &str {
+        &self.function.name
+    }
+}
+
+// A captured local. It stays in its slot while that is on the stack, and is moved out when the
+// slot is popped, so closures that outlive the call still share it.
+#[derive(Debug)]
+enum Capture {
+    Open(usize),
+    Closed(Value),
+}
+
+impl From<&Constant> for Value {
+    fn from(constant: &Constant) -> Self {
+        match constant {
+            Constant::Bool(value) => Value::Bool(*value),
+            Constant::UInt(value) => Value::UInt(*value),
+            Constant::Int(value) => Value::Int(*value),
+            Constant::Float(value) => Value::Float(*value),
+            Constant::String(value) => Value::String(Rc::clone(value)),
+            Constant::Function(function) => Value::Function(Rc::new(Closure { function: Rc::clone(function), captures: Vec::new() })),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, RuntimeError>;
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // Index of the first local slot on the stack. The callee sits just below it.
+    base: usize,
+}
+
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    constants: Vec<Value>,
+    globals: HashMap<Rc<str>, Value>,
+    // The captures of locals still on the stack, by slot.
+    open: Vec<(usize, Rc<RefCell<Capture>>)>,
+    // Where natives print to.
+    output: Box<dyn Write>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Vm::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        let mut globals = HashMap::new();
+        for native in Native::ALL {
+            globals.insert(Rc::from(native.name()), Value::Native(native));
+        }
+        Vm {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            constants: Vec::new(),
+            globals,
+            open: Vec::new(),
+            output: Box::new(std::io::stdout()),
+        }
+    }
+
+    /// Sends everything the program prints to `output` instead of stdout.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Runs `program` and then its `main` function, if it defines one.
+    pub fn run(&mut self, program: Program) -> Result<Value> {
+        let value = self.execute(program)?;
+        if self.global("main").is_some() {
+            self.call("main", vec![])
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Runs the top-level code of `program`, leaving its globals defined in the VM.
+    pub fn execute(&mut self, program: Program) -> Result<Value> {
+        self.constants = program.constants.iter().map(Value::from).collect();
+        let function = Rc::new(Function { name: String::from("<script>"), arity: 0, code: program.code });
+        let script = Rc::new(Closure { function, captures: Vec::new() });
+        self.close_captures(0);
+        self.stack.clear();
+        self.frames.clear();
+        self.stack.push(Value::Function(Rc::clone(&script)));
+        self.frames.push(Frame { closure: script, ip: 0, base: 1 });
+        self.dispatch()
+    }
+
+    /// Calls the global function `name` with `args`.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let callee = self.globals.get(name).cloned().ok_or_else(|| RuntimeError {
+            span: Span::default(),
+            details: format!("Undefined name `{}`.", name),
+        })?;
+        let argc = u8::try_from(args.len()).map_err(|_| RuntimeError {
+            span: Span::default(),
+            details: format!("Cannot pass {} arguments to `{}`, the limit is {}.", args.len(), name, u8::MAX),
+        })?;
+        self.close_captures(0);
+        self.stack.clear();
+        self.frames.clear();
+        self.stack.push(callee);
+        self.stack.extend(args);
+        match self.invoke(argc, Span::default())? {
+            Some(value) => Ok(value),
+            None => self.dispatch(),
+        }
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.globals.get(name)
+    }
+
+    fn dispatch(&mut self) -> Result<Value> {
+        loop {
+            let code = self.code();
+            let span = code.span_at(self.frame().ip);
+            let Some(&instruction) = code.bytes().get(self.frame().ip) else {
+                return Err(RuntimeError { span, details: String::from("Instruction pointer ran past the end of the code.") });
+            };
+            self.frame_mut().ip += 1;
+
+            match instruction {
+                op::LOAD => {
+                    let slot = self.frame().base + self.read_u8() as usize;
+                    let value = self.stack.get(slot).cloned().ok_or_else(|| underflow(span))?;
+                    self.stack.push(value);
+                }
+                op::LOAD_CONST => {
+                    let value = self.read_constant(span)?;
+                    self.stack.push(value);
+                }
+                op::STORE => {
+                    let slot = self.frame().base + self.read_u8() as usize;
+                    let value = self.peek(span)?.clone();
+                    *self.stack.get_mut(slot).ok_or_else(|| underflow(span))? = value;
+                }
+                op::INVOKE => {
+                    let argc = self.read_u8();
+                    if let Some(value) = self.invoke(argc, span)? {
+                        self.stack.push(value);
+                    }
+                }
+                op::LOAD_GLOBAL => {
+                    let name = self.read_name(span)?;
+                    let value = self.globals.get(&name).cloned().ok_or_else(|| RuntimeError {
+                        span,
+                        details: format!("Undefined name `{}`.", name),
+                    })?;
+                    self.stack.push(value);
+                }
+                op::STORE_GLOBAL => {
+                    let name = self.read_name(span)?;
+                    let value = self.peek(span)?.clone();
+                    self.globals.insert(name, value);
+                }
+                op::GET_PROPERTY => {
+                    let name = self.read_name(span)?;
+                    let obj = self.pop(span)?;
+                    return Err(RuntimeError {
+                        span,
+                        details: format!("Value of type {} has no property `{}`.", obj.type_name(), name),
+                    });
+                }
+                op::SET_PROPERTY => {
+                    let name = self.read_name(span)?;
+                    self.pop(span)?;
+                    let obj = self.pop(span)?;
+                    return Err(RuntimeError {
+                        span,
+                        details: format!("Value of type {} has no property `{}`.", obj.type_name(), name),
+                    });
+                }
+                op::DUP => {
+                    let value = self.peek(span)?.clone();
+                    self.stack.push(value);
+                }
+                op::UNIT => self.stack.push(Value::Unit),
+                op::POP => {
+                    self.close_captures(self.stack.len().saturating_sub(1));
+                    self.pop(span)?;
+                }
+                op::RETURN => {
+                    let value = self.pop(span)?;
+                    let frame = self.frames.pop().expect("no call frame");
+                    self.close_captures(frame.base - 1);
+                    self.stack.truncate(frame.base - 1);
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+                    self.stack.push(value);
+                }
+                op::ADD
+                | op::SUB
+                | op::MUL
+                | op::DIV
+                | op::MOD
+                | op::EQUAL
+                | op::NOT_EQUAL
+                | op::LESS
+                | op::LESS_EQUAL
+                | op::GREATER
+                | op::GREATER_EQUAL
+                | op::RANGE => {
+                    let rhs = self.pop(span)?;
+                    let lhs = self.pop(span)?;
+                    let value = value::binary(binary_op(instruction), lhs, rhs).map_err(|details| RuntimeError { span, details })?;
+                    self.stack.push(value);
+                }
+                op::JUMP => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip += offset;
+                }
+                op::JUMP_IF_FALSE | op::JUMP_IF_TRUE => {
+                    let offset = self.read_u16() as usize;
+                    let holds = self.peek(span)?.is_truthy();
+                    if holds == (instruction == op::JUMP_IF_TRUE) {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                op::LOOP => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+                op::FOR_ITER => {
+                    let offset = self.read_u16() as usize;
+                    let len = self.stack.len();
+                    if len < self.frame().base + 2 {
+                        return Err(underflow(span));
+                    }
+                    let Value::UInt(index) = self.stack[len - 1] else {
+                        return Err(RuntimeError { span, details: String::from("Loop counter is not a uint.") });
+                    };
+                    match self.stack[len - 2].iterate(index).map_err(|details| RuntimeError { span, details })? {
+                        Some(item) => {
+                            self.stack[len - 1] = Value::UInt(index + 1);
+                            self.stack.push(item);
+                        }
+                        None => self.frame_mut().ip += offset,
+                    }
+                }
+                op::LIST => {
+                    let count = self.read_u16() as usize;
+                    if self.stack.len() < self.frame().base + count {
+                        return Err(underflow(span));
+                    }
+                    let items = self.stack.split_off(self.stack.len() - count);
+                    self.stack.push(Value::List(Rc::new(items)));
+                }
+                op::FORMAT => {
+                    let count = self.read_u16() as usize;
+                    if self.stack.len() < self.frame().base + count {
+                        return Err(underflow(span));
+                    }
+                    let parts = self.stack.split_off(self.stack.len() - count);
+                    let string: String = parts.iter().map(Value::to_string).collect();
+                    self.stack.push(Value::String(Rc::from(string)));
+                }
+                op::NOT => {
+                    let value = self.pop(span)?;
+                    self.stack.push(Value::Bool(!value.is_truthy()));
+                }
+                op::NEG => {
+                    let value = self.pop(span)?;
+                    let value = value::negate(value).map_err(|details| RuntimeError { span, details })?;
+                    self.stack.push(value);
+                }
+                op::CLOSURE => {
+                    let Value::Function(closure) = self.read_constant(span)? else {
+                        return Err(RuntimeError { span, details: String::from("Expected a function constant.") });
+                    };
+                    let count = self.read_u8();
+                    let mut captures = Vec::with_capacity(count as usize);
+                    for _ in 0..count {
+                        let local = self.read_u8() == 1;
+                        let index = self.read_u8() as usize;
+                        let capture = if local {
+                            self.capture(self.frame().base + index)
+                        } else {
+                            self.frame().closure.captures.get(index).cloned().ok_or_else(|| missing_capture(span, index))?
+                        };
+                        captures.push(capture);
+                    }
+                    let function = Rc::clone(&closure.function);
+                    self.stack.push(Value::Function(Rc::new(Closure { function, captures })));
+                }
+                op::LOAD_CAPTURE => {
+                    let capture = self.read_capture(span)?;
+                    let value = match &*capture.borrow() {
+                        Capture::Open(slot) => self.stack.get(*slot).cloned().ok_or_else(|| underflow(span))?,
+                        Capture::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                op::STORE_CAPTURE => {
+                    let capture = self.read_capture(span)?;
+                    let value = self.peek(span)?.clone();
+                    match &mut *capture.borrow_mut() {
+                        Capture::Open(slot) => *self.stack.get_mut(*slot).ok_or_else(|| underflow(span))? = value,
+                        Capture::Closed(closed) => *closed = value,
+                    };
+                }
+                op::CLOSE_CAPTURES => {
+                    let slot = self.frame().base + self.read_u8() as usize;
+                    self.close_captures(slot);
+                }
+                _ => return Err(RuntimeError { span, details: format!("Unknown instruction {}.", instruction) }),
+            }
+        }
+    }
+
+    /// Calls the value below the top `argc` values. Native functions complete immediately and
+    /// return their result, while bytecode functions push a new frame and return `None`.
+    fn invoke(&mut self, argc: u8, span: Span) -> Result<Option<Value>> {
+        let argc = argc as usize;
+        if self.stack.len() < argc + 1 {
+            return Err(underflow(span));
+        }
+        let base = self.stack.len() - argc;
+        match self.stack[base - 1].clone() {
+            Value::Function(closure) => {
+                let function = &closure.function;
+                if function.arity as usize != argc {
+                    return Err(RuntimeError {
+                        span,
+                        details: format!("Function `{}` takes {} arguments but {} were given.", function.name, function.arity, argc),
+                    });
+                }
+                if self.frames.len() >= MAX_FRAMES {
+                    return Err(RuntimeError { span, details: String::from("Stack overflow.") });
+                }
+                self.frames.push(Frame { closure, ip: 0, base });
+                Ok(None)
+            }
+            Value::Native(native) => {
+                let args = self.stack.split_off(base);
+                self.stack.pop();
+                native.call(&args, &mut self.output).map(Some).map_err(|details| RuntimeError { span, details })
+            }
+            callee => Err(RuntimeError { span, details: format!("Cannot call a value of type {}.", callee.type_name()) }),
+        }
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("no call frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("no call frame")
+    }
+
+    fn code(&self) -> &ByteStream {
+        &self.frame().closure.function.code
+    }
+
+    fn read_u8(&mut self) -> u8 {
+        let frame = self.frames.last_mut().expect("no call frame");
+        let byte = frame.closure.function.code.bytes().get(frame.ip).copied().unwrap_or_default();
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        u16::from_be_bytes([self.read_u8(), self.read_u8()])
+    }
+
+    fn read_constant(&mut self, span: Span) -> Result<Value> {
+        let index = self.read_u16() as usize;
+        self.constants.get(index).cloned().ok_or_else(|| RuntimeError {
+            span,
+            details: format!("Constant {} does not exist.", index),
+        })
+    }
+
+    fn read_name(&mut self, span: Span) -> Result<Rc<str>> {
+        match self.read_constant(span)? {
+            Value::String(name) => Ok(name),
+            other => Err(RuntimeError { span, details: format!("Expected a name constant but found {}.", other.type_name()) }),
+        }
+    }
+
+    fn read_capture(&mut self, span: Span) -> Result<Rc<RefCell<Capture>>> {
+        let index = self.read_u8() as usize;
+        self.frame().closure.captures.get(index).cloned().ok_or_else(|| missing_capture(span, index))
+    }
+
+    // The capture of the local in `slot`, shared by every closure that captures it.
+    fn capture(&mut self, slot: usize) -> Rc<RefCell<Capture>> {
+        let index = self.open.partition_point(|(open, _)| *open < slot);
+        match self.open.get(index) {
+            Some((open, capture)) if *open == slot => Rc::clone(capture),
+            _ => {
+                let capture = Rc::new(RefCell::new(Capture::Open(slot)));
+                self.open.insert(index, (slot, Rc::clone(&capture)));
+                capture
+            }
+        }
+    }
+
+    // Moves the captured locals in `slot` and above off the stack.
+    fn close_captures(&mut self, slot: usize) {
+        while self.open.last().is_some_and(|(open, _)| *open >= slot) {
+            let (open, capture) = self.open.pop().expect("no open capture");
+            let value = self.stack[open].clone();
+            *capture.borrow_mut() = Capture::Closed(value);
+        }
+    }
+
+    fn pop(&mut self, span: Span) -> Result<Value> {
+        if self.stack.len() <= self.frame().base {
+            return Err(underflow(span));
+        }
+        Ok(self.stack.pop().expect("stack is not empty"))
+    }
+
+    fn peek(&self, span: Span) -> Result<&Value> {
+        if self.stack.len() <= self.frame().base {
+            return Err(underflow(span));
+        }
+        Ok(self.stack.last().expect("stack is not empty"))
+    }
+}
+
+fn underflow(span: Span) -> RuntimeError {
+    RuntimeError { span, details: String::from("Stack underflow.") }
+}
+
+fn missing_capture(span: Span, index: usize) -> RuntimeError {
+    RuntimeError { span, details: format!("Capture {} does not exist.", index) }
+}
+
+// The operator a binary instruction applies.
+fn binary_op(instruction: u8) -> BinaryOp {
+    match instruction {
+        op::ADD => BinaryOp::Add,
+        op::SUB => BinaryOp::Sub,
+        op::MUL => BinaryOp::Mul,
+        op::DIV => BinaryOp::Div,
+        op::MOD => BinaryOp::Mod,
+        op::EQUAL => BinaryOp::Equal,
+        op::NOT_EQUAL => BinaryOp::NotEqual,
+        op::LESS => BinaryOp::Less,
+        op::LESS_EQUAL => BinaryOp::LessEqual,
+        op::GREATER => BinaryOp::Greater,
+        op::GREATER_EQUAL => BinaryOp::GreaterEqual,
+        op::RANGE => BinaryOp::Range,
+        _ => unreachable!("{} is not a binary instruction", instruction),
+    }
+}
+
+/// Runs `program` and then its `main` function, if it defines one.
+pub fn run(program: Program) -> Result<Value> {
+    Vm::new().run(program)
+}
@@ -0,0 +1,102 @@
+//! Compiler tests. Each test compiles a snippet and checks the emitted instructions and the
+//! constant pool directly.
+
+extern crate circuit_lang as circuit;
+
+use std::rc::Rc;
+
+use circuit::bytecode::{op, Constant, Program};
+use circuit::compiler::{self, CompileError};
+use circuit::modules;
+use circuit::source_map::SourceMap;
+
+fn compile(src: &str) -> Result<Program, Vec<CompileError>> {
+    let mut sources = SourceMap::new();
+    let file = sources.add("main.cir", src);
+    let graph = modules::load(&mut sources, file).unwrap_or_else(|errors| panic!("`{}` failed to load: {:?}", src, errors));
+    compiler::compile(&graph, &sources)
+}
+
+fn string(value: &str) -> Constant {
+    Constant::String(Rc::from(value))
+}
+
+#[test]
+fn arithmetic() {
+    let program = compile("1 + 2 * 3;").unwrap();
+    assert_eq!(program.constants, [Constant::UInt(1), Constant::UInt(2), Constant::UInt(3)]);
+    #[rustfmt::skip]
+    let expected = [
+        op::LOAD_CONST, 0, 0,
+        op::LOAD_CONST, 0, 1,
+        op::LOAD_CONST, 0, 2,
+        op::MUL,
+        op::ADD,
+        op::POP,
+        op::UNIT,
+        op::RETURN,
+    ];
+    assert_eq!(program.code.bytes(), expected);
+}
+
+#[test]
+fn constants_are_shared() {
+    let program = compile("1 + 1; \"a\" + -1; \"a\";").unwrap();
+    assert_eq!(program.constants, [Constant::UInt(1), string("a"), Constant::Int(-1)]);
+}
+
+#[test]
+fn property_access() {
+    let program = compile("print.b;").unwrap();
+    assert_eq!(program.constants, [string("print"), string("b")]);
+    assert_eq!(program.code.bytes(), [op::LOAD_GLOBAL, 0, 0, op::GET_PROPERTY, 0, 1, op::POP, op::UNIT, op::RETURN]);
+}
+
+#[test]
+fn functions_are_globals() {
+    let program = compile("fun main() { print(\"hi\"); }").unwrap();
+    let Constant::Function(function) = &program.constants[2] else {
+        panic!("expected a function constant, found {:?}", program.constants);
+    };
+    assert_eq!((function.name.as_str(), function.arity), ("main", 0));
+    assert_eq!(function.code.bytes(), [op::LOAD_GLOBAL, 0, 0, op::LOAD_CONST, 0, 1, op::INVOKE, 1, op::POP, op::UNIT, op::RETURN]);
+    assert_eq!(program.constants[..2], [string("print"), string("hi")]);
+    assert_eq!(program.constants[3..], [string("main")]);
+    assert_eq!(program.code.bytes(), [op::LOAD_CONST, 0, 2, op::STORE_GLOBAL, 0, 3, op::POP, op::UNIT, op::RETURN]);
+}
+
+#[test]
+fn nested_functions_are_locals() {
+    let program = compile("fun outer() { fun inner() {} inner(); }").unwrap();
+    let Constant::Function(outer) = &program.constants[1] else {
+        panic!("expected a function constant, found {:?}", program.constants);
+    };
+    // `inner` is the first local, in slot 0.
+    assert_eq!(outer.code.bytes(), [op::LOAD_CONST, 0, 0, op::LOAD, 0, op::INVOKE, 0, op::POP, op::UNIT, op::RETURN]);
+}
+
+#[test]
+fn captures_are_closures() {
+    let program = compile("fun outer() { fun inner() {} fun other() { inner(); } }").unwrap();
+    let (Constant::Function(other), Constant::Function(outer)) = (&program.constants[1], &program.constants[2]) else {
+        panic!("expected function constants, found {:?}", program.constants);
+    };
+    // `other` captures `inner` from slot 0 of `outer`, and loads it as its capture 0.
+    #[rustfmt::skip]
+    let expected = [
+        op::LOAD_CONST, 0, 0,
+        op::CLOSURE, 0, 1, 1, 1, 0,
+        op::UNIT,
+        op::RETURN,
+    ];
+    assert_eq!(outer.code.bytes(), expected);
+    assert_eq!(other.code.bytes(), [op::LOAD_CAPTURE, 0, op::INVOKE, 0, op::POP, op::UNIT, op::RETURN]);
+}
+
+#[test]
+fn too_many_locals_is_an_error() {
+    // Slot 0 holds the function itself, so the 256th local doesn't fit in a byte.
+    let locals: String = (0..256).map(|i| format!("let a{} = {}; ", i, i)).collect();
+    let errors = compile(&format!("fun f() {{ {}{{ let b = 1; 2 }} }}", locals)).unwrap_err();
+    assert_eq!(errors[0].details, "Too many local variables in function.");
+}
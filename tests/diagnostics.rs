@@ -0,0 +1,105 @@
+//! Diagnostic rendering tests. Each case builds a diagnostic against some source files and its
+//! rendering is compared with `tests/snapshots/diagnostics/<name>.snap`. Run with
+//! `UPDATE_SNAPSHOTS=1` to rewrite the snapshots.
+
+extern crate circuit_lang as circuit;
+
+use std::path::PathBuf;
+
+use circuit::diagnostics::{Diagnostic, Severity};
+use circuit::lexer::token::TokenKind;
+use circuit::parser::ParseError;
+use circuit::source_map::SourceMap;
+use circuit::span::{FileId, Span};
+
+const MAIN: &str = "fun main() {\n    let total = add(1,\n        2);\n    print(total);\n}\n";
+const LIB: &str = "/// Adds numbers.\nfun add(a, b) {\n\ta + b\n}\n";
+
+type Case = fn(&SourceMap, FileId, FileId) -> Diagnostic;
+
+const CASES: &[(&str, Case)] = &[
+    ("single_line", |_, main, _| Diagnostic::error("Cannot find `total`.", span(main, MAIN, "print(total)")).with_label("not found")),
+    ("multi_line", |_, main, _| Diagnostic::error("Wrong arguments.", span(main, MAIN, "add(1,\n        2)")).with_label("in this call")),
+    ("secondary", |_, main, _| {
+        Diagnostic::error("Mismatched types.", span(main, MAIN, "print(total)"))
+            .with_secondary(span(main, MAIN, "main"), "in this function")
+            .with_secondary(span(main, MAIN, "let total"), "declared here")
+    }),
+    ("other_file", |_, main, lib| {
+        Diagnostic::error("Function `add` takes 2 arguments.", span(main, MAIN, "add"))
+            .with_secondary(span(lib, LIB, "add"), "declared here")
+    }),
+    ("tabs", |_, _, lib| Diagnostic::error("Cannot apply '+'.", span(lib, LIB, "+")).with_label("here")),
+    ("notes_and_help", |_, main, _| {
+        Diagnostic::new(Severity::Warning, "Unused variable.", span(main, MAIN, "let total"))
+            .with_note("variables are used when read")
+            .with_help("remove it")
+    }),
+    ("parse_error", |_, main, _| {
+        let error = ParseError {
+            span: span(main, MAIN, ";"),
+            found: Some(TokenKind::Semi),
+            expected: vec![TokenKind::RParen, TokenKind::Comma],
+            details: String::from("Expected ')' after arguments."),
+        };
+        Diagnostic::from(&error)
+    }),
+    ("end_of_input", |sources, main, _| {
+        let end = sources.src(main).len();
+        Diagnostic::error("Expected '}'.", Span::new(main, end, end)).with_label("found end of input")
+    }),
+    ("unknown_file", |_, main, _| {
+        Diagnostic::error("Stack overflow.", Span::new(FileId(7), 0, 1))
+            .with_secondary(span(main, MAIN, "add"), "called from here")
+            .with_note("the call came from the host")
+    }),
+];
+
+// The span of the first occurrence of `text` in `src`.
+fn span(file: FileId, src: &str, text: &str) -> Span {
+    let start = src.find(text).unwrap_or_else(|| panic!("`{}` is not in the source", text));
+    Span::new(file, start, start + text.len())
+}
+
+fn render(case: Case, colour: bool) -> String {
+    let mut sources = SourceMap::new();
+    let main = sources.add("main.cir", MAIN);
+    let lib = sources.add("lib.cir", LIB);
+    let rendered = case(&sources, main, lib).render(&sources, colour);
+    // Escapes would make the snapshots unreadable.
+    rendered.replace('\x1b', "\\e")
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/diagnostics").join(format!("{}.snap", name))
+}
+
+#[test]
+fn snapshots() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut failures = vec![];
+    let cases = CASES.iter().map(|&(name, case)| (String::from(name), render(case, false)));
+    // One case in colour, to check where the escapes go.
+    let (name, case) = CASES[0];
+    let coloured = (format!("{}_colour", name), render(case, true));
+    for (name, actual) in cases.chain([coloured]) {
+        let path = snapshot_path(&name);
+        if update {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!("{}:\n--- expected\n{}--- actual\n{}", name, expected, actual)),
+            Err(_) => failures.push(format!("{}: missing snapshot {}", name, path.display())),
+        }
+    }
+    assert!(failures.is_empty(), "diagnostic snapshots differ (run with UPDATE_SNAPSHOTS=1 to accept):\n\n{}", failures.join("\n"));
+}
+
+#[test]
+fn unknown_file_renders_the_message() {
+    let diagnostic = Diagnostic::error("Stack overflow.", Span::default()).with_label("here");
+    assert_eq!(diagnostic.render(&SourceMap::new(), false), "error: Stack overflow.\n");
+}
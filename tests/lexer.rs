@@ -0,0 +1,166 @@
+//! Table-driven lexer tests. Each case is lexed and rendered as one line per token, or per error,
+//! and compared with `tests/snapshots/lexer/<name>.snap`. Run with `UPDATE_SNAPSHOTS=1` to rewrite
+//! the snapshots after an intended change, then review the diff.
+
+extern crate circuit_lang as circuit;
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use circuit::lexer::{self, token::TokenKind};
+use circuit::source_map::SourceMap;
+use circuit::span::{FileId, FileIndex};
+use circuit::Tok;
+
+const CASES: &[(&str, &str)] = &[
+    ("operators", "+ - * / % += -= *= /= = == ! != < <= > >= && || . .. ; : :: -> ,"),
+    ("delimiters", "( ) { } [ ] ({[]})"),
+    ("keywords", "if else for in while break continue return fun let mut use mod pub as super crate true false"),
+    ("identifiers", "x foo _bar snake_case camelCase x1 iffy funny letter"),
+    ("integers", "0 42 1_000_000 0xff 0xFF_i 0o17 0b1010 7u 3i 18446744073709551615"),
+    ("floats", "1.5 0.25 2e10 1.5e-3 6E+2 4f 1_000.000_1"),
+    ("ranges", "0..5 1..2.0 a..b"),
+    ("strings", r#""" "plain" "esc \n \t \\ \" \u{41}" r"raw \n {x}" "{{braces}}""#),
+    ("interpolation", r#""a {x} b {f("c")} d" next"#),
+    ("placeholders", r#""{0} and {12}" "{0i}" "{ 0}""#),
+    ("paths", "use crate::a::b as c; super::x a::*; a:::b"),
+    ("annotations", "fun f(a: int, b: [uint]) -> fun(int) -> bool { let x: float = a--b; x->y }"),
+    ("labels", "'outer: while x { break 'outer; continue 'outer; }"),
+    ("comments", "a // line\nb /* block /* nested */ */ c\n//// not a doc\nd"),
+    ("doc_comments", "/// Adds numbers.\n///\nfun add(a, b) { a + b }"),
+    ("assignment", "let mut x = 1; x += 2; x == 3; y != x;"),
+    ("whitespace", "a\r\n\tb\u{b}c\u{c}d\n"),
+    ("unicode", "let größe = \"日本 {größe} 😀\";\n\u{a0}π\u{3000}é\u{301}"),
+    ("program", "fun main() {\n    for i in 0..3 {\n        print(\"{i}\");\n    }\n}\n"),
+    ("error_unknown_character", "a @ b # c"),
+    ("error_lone_ampersand", "a & b | c"),
+    ("error_unterminated_string", "\"open"),
+    ("error_unterminated_comment", "a /* open /* nested */"),
+    ("error_escape", r#""bad \q" "\u{110000}" "\u41""#),
+    ("error_interpolation", r#""{}" "a } b" "{x"#),
+    ("error_numbers", "99999999999999999999 9223372036854775808i 0x 1.5i 12abc 1e999"),
+    ("error_label", "' x"),
+    ("error_unicode", "größe § 😀 \"ü"),
+];
+
+// `line:column`, with the UTF-16 column after a slash when it differs.
+fn position(index: FileIndex) -> String {
+    if index.utf16_column == index.column {
+        format!("{}:{}", index.line, index.column)
+    } else {
+        format!("{}:{}/{}", index.line, index.column, index.utf16_column)
+    }
+}
+
+fn render(src: &str) -> String {
+    let mut sources = SourceMap::new();
+    let file = sources.add("case", src);
+    let mut out = format!("input: {:?}\n", src);
+    match lexer::tokenize(src, file) {
+        Ok(tokens) => {
+            for tok in tokens {
+                let span = tok.span();
+                let (start, end) = (sources.start(span), sources.end(span));
+                let text = sources.span_str(span);
+                let _ = writeln!(out, "{:?} {:?} {}..{} {}-{}", tok.kind, text, span.start, span.end, position(start), position(end));
+            }
+        }
+        Err(errors) => {
+            for error in errors {
+                let span = error.span;
+                let (start, end) = (sources.start(span), sources.end(span));
+                let _ = writeln!(out, "error {}..{} {}-{} {}", span.start, span.end, position(start), position(end), error.details);
+            }
+        }
+    }
+    out
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/lexer").join(format!("{}.snap", name))
+}
+
+#[test]
+fn snapshots() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut failures = vec![];
+    for (name, src) in CASES {
+        let actual = render(src);
+        let path = snapshot_path(name);
+        if update {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!("{}:\n--- expected\n{}--- actual\n{}", name, expected, actual)),
+            Err(_) => failures.push(format!("{}: missing snapshot {}", name, path.display())),
+        }
+    }
+    assert!(failures.is_empty(), "lexer snapshots differ (run with UPDATE_SNAPSHOTS=1 to accept):\n\n{}", failures.join("\n"));
+}
+
+// Every token kind. The match has no wildcard, so a new TokenKind won't compile until it's listed
+// here, and `covers_every_token_kind` then fails until a case produces it.
+fn all_kinds() -> Vec<TokenKind> {
+    use TokenKind::*;
+    let kinds = vec![
+        Plus, Minus, Star, Slash, Percent, PlusEq, MinusEq, StarEq, SlashEq, True, False, UInt, Int, Float, String,
+        Ident, DocComment, If, Else, For, In, While, Break, Continue, Return, Fun, Let, Mut, Use, Mod, Pub, As, Super,
+        Crate, Label, LParen, RParen, LBrace, RBrace, LBracket, RBracket, Eq, EqEq, Bang, BangEq, Lt, LtEq, Gt, GtEq,
+        AndAnd, OrOr, Dot, DotDot, Semi, Colon, ColonColon, Arrow, Comma,
+    ];
+    for kind in &kinds {
+        match kind {
+            Plus | Minus | Star | Slash | Percent | PlusEq | MinusEq | StarEq | SlashEq | True | False | UInt | Int
+            | Float | String | Ident | DocComment | If | Else | For | In | While | Break | Continue | Return | Fun
+            | Let | Mut | Use | Mod | Pub | As | Super | Crate | Label | LParen | RParen | LBrace | RBrace
+            | LBracket | RBracket | Eq | EqEq | Bang | BangEq | Lt | LtEq | Gt | GtEq | AndAnd | OrOr | Dot
+            | DotDot | Semi | Colon | ColonColon | Arrow | Comma => (),
+        }
+    }
+    kinds
+}
+
+#[test]
+fn covers_every_token_kind() {
+    let mut seen = HashSet::new();
+    for (_, src) in CASES {
+        if let Ok(tokens) = lexer::tokenize(src, FileId::default()) {
+            seen.extend(tokens.map(|tok| format!("{:?}", tok.kind)));
+        }
+    }
+    let missing: Vec<TokenKind> = all_kinds().into_iter().filter(|kind| !seen.contains(&format!("{:?}", kind))).collect();
+    assert!(missing.is_empty(), "no lexer case produces {:?}", missing);
+}
+
+fn kinds(src: &str) -> Vec<TokenKind> {
+    lexer::tokenize(src, FileId::default()).unwrap().map(|tok| tok.kind).collect()
+}
+
+#[test]
+fn tok_macro_matches_lexer() {
+    assert_eq!(kinds("="), [Tok![=]]);
+    assert_eq!(kinds("=="), [Tok![==]]);
+    assert_eq!(kinds("= =="), [Tok![=], Tok![==]]);
+    assert_eq!(kinds("!="), [Tok![!=]]);
+    assert_eq!(kinds("fun"), [Tok![fun]]);
+    assert_eq!(kinds("let mut"), [Tok![let], Tok![mut]]);
+    assert_eq!(kinds("<= >= && ||"), [Tok![<=], Tok![>=], Tok![&&], Tok![||]]);
+    assert_eq!(kinds("+= -= *= /= %"), [Tok![+=], Tok![-=], Tok![*=], Tok![/=], Tok![%]]);
+    assert_eq!(kinds(".. . : ;"), [Tok![..], Tok![.], Tok![:], Tok![;]]);
+    assert_eq!(kinds("use mod pub as"), [Tok![use], Tok![mod], Tok![pub], Tok![as]]);
+    assert_eq!(kinds("super::crate:::"), [Tok![super], Tok![::], Tok![crate], Tok![::], Tok![:]]);
+    assert_eq!(kinds("-> - >--"), [Tok![->], Tok![-], Tok![>], Tok![-], Tok![-]]);
+}
+
+#[test]
+fn long_runs_of_comments() {
+    // Skipping these once recursed per comment and overflowed the stack.
+    let src = format!("a{}b", "// line\n/* block */ \n".repeat(100_000));
+    assert_eq!(kinds(&src), [TokenKind::Ident, TokenKind::Ident]);
+    let src = format!("a{}/// doc\nb", "//// not a doc\n".repeat(100_000));
+    assert_eq!(kinds(&src), [TokenKind::Ident, TokenKind::DocComment, TokenKind::Ident]);
+}
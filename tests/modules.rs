@@ -0,0 +1,82 @@
+//! Module tests. Each case is a directory under `tests/modules` whose `main.cir` is loaded along with
+//! the modules it declares. Programs that load and type check are run by both the interpreter and
+//! the VM, which have to agree, and any warnings followed by the result or the rendered errors are
+//! compared with `tests/snapshots/modules/<name>.snap`. Run with `UPDATE_SNAPSHOTS=1` to rewrite
+//! the snapshots.
+
+extern crate circuit_lang as circuit;
+
+use std::path::{Path, PathBuf};
+
+use circuit::diagnostics::Diagnostic;
+use circuit::source_map::SourceMap;
+use circuit::{compiler, interp, modules, types, vm};
+
+const CASES: &[&str] = &[
+    "basic",
+    "glob",
+    "super_crate",
+    "locals",
+    "shadowing",
+    "typed",
+    "private",
+    "missing_file",
+    "unresolved",
+    "undefined",
+    "type_errors",
+    "cycle",
+    "duplicate",
+    "captured_read",
+    "captured_write",
+    "captured_block",
+    "recursive_local",
+];
+
+fn render(name: &str) -> String {
+    let mut sources = SourceMap::new();
+    // Relative to the package root, so file names in the snapshots don't depend on the checkout.
+    let path = Path::new("tests/modules").join(name).join("main.cir");
+    let file = sources.load(&path).unwrap();
+    let graph = match modules::load(&mut sources, file) {
+        Ok(graph) => graph,
+        Err(diagnostics) => return diagnostics.iter().map(|diagnostic| diagnostic.render(&sources, false)).collect(),
+    };
+    let warnings: String = graph.warnings().iter().map(|warning| warning.render(&sources, false)).collect();
+    let errors = types::check(&graph, &sources);
+    if !errors.is_empty() {
+        return errors.iter().map(|error| Diagnostic::from(error).render(&sources, false)).collect::<String>() + &warnings;
+    }
+    let interpreted = interp::run(&graph, &sources).map_err(|error| Diagnostic::from(&error).render(&sources, false));
+    let program = compiler::compile(&graph, &sources).unwrap_or_else(|errors| panic!("{}: {:?}", name, errors));
+    let compiled = vm::run(program).map_err(|error| Diagnostic::from(&error).render(&sources, false));
+    match (interpreted, compiled) {
+        (Ok(a), Ok(b)) if a.to_string() == b.to_string() => format!("{}result: {}\n", warnings, a),
+        (Err(a), Err(b)) if a == b => warnings + &a,
+        (a, b) => panic!("{}: the interpreter gave {:?} but the VM gave {:?}", name, a.map(|v| v.to_string()), b.map(|v| v.to_string())),
+    }
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/modules").join(format!("{}.snap", name))
+}
+
+#[test]
+fn snapshots() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut failures = vec![];
+    for name in CASES {
+        let actual = render(name);
+        let path = snapshot_path(name);
+        if update {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!("{}:\n--- expected\n{}--- actual\n{}", name, expected, actual)),
+            Err(_) => failures.push(format!("{}: missing snapshot {}", name, path.display())),
+        }
+    }
+    assert!(failures.is_empty(), "module snapshots differ (run with UPDATE_SNAPSHOTS=1 to accept):\n\n{}", failures.join("\n"));
+}
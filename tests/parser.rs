@@ -0,0 +1,263 @@
+//! Table-driven parser tests. Each case is parsed, recovering from errors, and rendered as an
+//! outline of the statements followed by one line per error, then compared with
+//! `tests/snapshots/parser/<name>.snap`. Run with `UPDATE_SNAPSHOTS=1` to rewrite the snapshots.
+
+extern crate circuit_lang as circuit;
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use circuit::lexer::{self, token::Token};
+use circuit::parser::ast::*;
+use circuit::parser::{self, ParseStream};
+use circuit::source_map::SourceMap;
+use circuit::span::Span;
+
+const CASES: &[(&str, &str)] = &[
+    ("expressions", "1 + 2 * 3; f(a, b)(c); (1 + 2) * 3; -a.b; !\"s\"; [1, 2, [3]]; [];"),
+    ("functions", "fun f() { g(); } fun h() { fun i() {} }"),
+    ("error_expected", "f(1, 2; 1 +;"),
+    ("error_semicolon", "f()\ng();"),
+    ("error_found_end", "fun f("),
+    ("program", "fun main() { helper(); }\nfun helper() {}\nmain();"),
+    ("error_trailing_input", "fun main() {}\n1 + 2\n}"),
+    ("parameters", "fun f() {} fun g(a) {} fun h(a, b,) {}"),
+    ("error_parameters", "fun f(a, a) {} fun g(a b) {} fun h(,) {} fun k(a, b, a) {}"),
+    ("returns", "fun f() { return; } fun g() { return 2 + 2; } fun h() { fun i() { return i; } return i(); }"),
+    ("error_return_outside", "return 1; fun f() {} return"),
+    ("blocks", "f({ return 2 + 2; }); { g(); } fun g() { { 1 } } fun h() { { i(); } 1 + 2 }"),
+    ("error_blocks", "{ f(); fun g() { { 1 }"),
+    ("if_else", "f(if a { 1 } else if b { 2 } else { 3 }); if c { d(); } if e {} else {} f(if g { h } else { i });"),
+    ("error_if_else", "if { 1 } if a 1; if a {} else 2;"),
+    ("loops", "while a { b(); } for x in 0..3 { continue; } 'outer: for x in xs { 'inner: while x { break 'outer; } }"),
+    ("error_loops", "break; fun f() { continue; } while a { break 'missing; } 'label: f();"),
+    ("assignment", "let x = 1; let mut y = 2; y = 3; y += 1; y -= 1; y *= 2; y /= 2; a = b = c; p.q = r;"),
+    ("error_assignment", "1 = 2; f() += 3; let mut = 4; let x 5;"),
+    ("precedence", "a || b && c == d < e + f * g % h; !a == -b; a != b || c >= d && e <= f; a..b + 1; -a * b; a > b == c;"),
+    ("numbers", "0xff; 1_000i; 2.5e3; 4f; -1; 1..2;"),
+    ("error_numbers", "99999999999999999999; 1.5i;"),
+    ("strings", r#""esc \n \t \\ \" \u{41}"; r"raw \n {x}"; "a {x} b {f("c") + 1} d"; "{{braces}}";"#),
+    ("error_strings", r#""bad \q"; "{}"; "a } b"; "{x"#),
+    ("doc_comments", "/// Adds numbers.\n///\n/// Both must be ints.\nfun add(a, b) { a + b }\n//// Not a doc.\nfun sub(a, b) { a - b }\n/* /// inside /* nested */ */\n/// Entry.\n// plain\nfun main() { add(1, /* inline */ 2); }"),
+    ("error_doc_comments", "/// Dangling.\nlet x = 1; { /// Also dangling.\n}"),
+    ("error_unterminated_comment", "a /* open /* nested */"),
+    ("whitespace", "a;\r\n\tb;\u{b}c\u{c}+ d;\r\n\u{a0}e\u{3000};"),
+    ("error_unknown_character", "a @ b; c # d; \"{e $ f}\";"),
+    ("modules", "mod util;\npub mod shapes;\nuse util::helper as h;\npub use crate::shapes::*;\nuse super::x;\npub fun main() { h(); shapes::area(util::consts::TWO); }"),
+    ("error_modules", "use ; use a::; mod a::b; use a as ; pub let x = 1;"),
+    ("annotations", "fun t(a: int, b: [uint], c) -> bool { true } let x: float = 1.5; let mut f: fun(int, string) -> [bool] = g; fun u() -> unit {}"),
+    ("error_annotations", "fun f(a: ) {} let x: = 1; fun g() -> {} let y: [int = 2;"),
+    ("recovery", "f(; fun f() { 1 +; g(); } h() i(); fun j() { 1 + }\nk();"),
+];
+
+// Renders statements as s-expressions, with names and operators as they appear in the source.
+struct Outline<'a> {
+    src: &'a str,
+}
+
+impl Outline<'_> {
+    fn text(&self, span: Span) -> &str {
+        &self.src[span.range()]
+    }
+
+    fn statement(&self, stmt: &AbstractStatement) -> String {
+        match stmt {
+            AbstractStatement::Expr(expr) => format!("{};", self.expression(expr)),
+            AbstractStatement::Block(block) => self.block(block),
+            AbstractStatement::FunctionDecl(decl) => {
+                let params: Vec<String> = decl
+                    .arguments
+                    .iter()
+                    .map(|arg| match &arg.ty {
+                        Some(ty) => format!("{}: {}", self.text(arg.ident.span()), self.text(ty.span)),
+                        None => String::from(self.text(arg.ident.span())),
+                    })
+                    .collect();
+                let docs: String = decl.doc_lines(self.src).iter().map(|line| format!("(doc {:?}) ", line)).collect();
+                let returns = decl.returns.as_ref().map(|ty| format!(" -> {}", self.text(ty.span))).unwrap_or_default();
+                let public = if decl.public { "pub " } else { "" };
+                format!("{}({}fun {} ({}){} {})", docs, public, self.text(decl.ident.span()), params.join(" "), returns, self.block(&decl.body))
+            }
+            AbstractStatement::Let(let_) => {
+                let mutable = if let_.mutable { "mut " } else { "" };
+                let ty = let_.ty.as_ref().map(|ty| format!(": {}", self.text(ty.span))).unwrap_or_default();
+                format!("(let {}{}{} {})", mutable, self.text(let_.ident.span()), ty, self.expression(&let_.value))
+            }
+            AbstractStatement::Return(ret) => match &ret.value {
+                Some(value) => format!("(return {})", self.expression(value)),
+                None => String::from("(return)"),
+            },
+            AbstractStatement::If(if_) => self.if_else(if_),
+            AbstractStatement::While(while_) => {
+                format!("({}while {} {})", self.label(while_.label), self.expression(&while_.condition), self.block(&while_.body))
+            }
+            AbstractStatement::For(for_) => format!(
+                "({}for {} {} {})",
+                self.label(for_.label),
+                self.text(for_.binding.span()),
+                self.expression(&for_.iterable),
+                self.block(&for_.body)
+            ),
+            AbstractStatement::Break(control) => format!("(break{})", self.control(control)),
+            AbstractStatement::Continue(control) => format!("(continue{})", self.control(control)),
+            AbstractStatement::Module(module) => format!("(mod {})", self.text(module.ident.span())),
+            AbstractStatement::Use(use_) => match use_.kind {
+                UseKind::Single { alias: Some(alias) } => format!("(use {} as {})", self.text(use_.path.span()), self.text(alias.span())),
+                UseKind::Single { alias: None } => format!("(use {})", self.text(use_.path.span())),
+                UseKind::Glob(_) => format!("(use {}::*)", self.text(use_.path.span())),
+            },
+            AbstractStatement::Error(span) => format!("(error {}..{})", span.start, span.end),
+        }
+    }
+
+    fn expression(&self, expr: &AbstractExpression) -> String {
+        match expr {
+            AbstractExpression::Grouping(inner) => format!("(group {})", self.expression(inner)),
+            AbstractExpression::Binary(binary) => {
+                format!("({} {} {})", self.text(binary.operator.span()), self.expression(&binary.lhs), self.expression(&binary.rhs))
+            }
+            AbstractExpression::Literal(literal) => format!("{:?}", literal.value),
+            AbstractExpression::BlockExpression(block) => format!("(block {})", self.block(block)),
+            AbstractExpression::TrailingBlock(block) => format!("(trailing {})", self.block(block)),
+            AbstractExpression::PropertyAccess(access) => match &access.obj {
+                Some(obj) => format!("(. {} {})", self.expression(obj), self.text(access.property.span())),
+                None => String::from(self.text(access.property.span())),
+            },
+            AbstractExpression::Unary(unary) => format!("({} {})", self.text(unary.op.span()), self.expression(&unary.expr)),
+            AbstractExpression::Call(call) => {
+                let args: Vec<String> = call.args.iter().map(|arg| format!(" {}", self.expression(arg))).collect();
+                format!("(call {}{})", self.expression(&call.expr), args.concat())
+            }
+            AbstractExpression::If(if_) => self.if_else(if_),
+            AbstractExpression::Assign(assign) => {
+                let target = self.expression(&AbstractExpression::PropertyAccess(assign.target.clone()));
+                format!("({} {} {})", self.text(assign.operator.span()), target, self.expression(&assign.value))
+            }
+            AbstractExpression::List(list) => {
+                let items: Vec<String> = list.items.iter().map(|item| self.expression(item)).collect();
+                format!("[{}]", items.join(" "))
+            }
+            AbstractExpression::Format(format) => {
+                let parts: Vec<String> = format
+                    .parts
+                    .iter()
+                    .map(|part| match part {
+                        FormatPart::Text(text) => format!(" {:?}", text),
+                        FormatPart::Expr(expr) => format!(" {}", self.expression(expr)),
+                    })
+                    .collect();
+                format!("(format{})", parts.concat())
+            }
+            AbstractExpression::Path(path) => String::from(self.text(path.span())),
+        }
+    }
+
+    fn block(&self, block: &Block) -> String {
+        let mut parts: Vec<String> = block.stmts.iter().map(|stmt| self.statement(stmt)).collect();
+        if let Some(value) = &block.value {
+            parts.push(format!("=> {}", self.expression(value)));
+        }
+        format!("{{{}}}", parts.join(" "))
+    }
+
+    fn if_else(&self, if_: &If) -> String {
+        let otherwise = match &if_.otherwise {
+            Some(Else::If(nested)) => format!(" else {}", self.if_else(nested)),
+            Some(Else::Block(block)) => format!(" else {}", self.block(block)),
+            None => String::new(),
+        };
+        format!("(if {} {}{})", self.expression(&if_.condition), self.block(&if_.then), otherwise)
+    }
+
+    fn label(&self, label: Option<Token>) -> String {
+        label.map(|label| format!("{} ", self.text(label.span()))).unwrap_or_default()
+    }
+
+    fn control(&self, control: &LoopControl) -> String {
+        control.label.map(|label| format!(" {}", self.text(label.span()))).unwrap_or_default()
+    }
+}
+
+fn render(src: &str) -> String {
+    let mut sources = SourceMap::new();
+    let file = sources.add("case", src);
+    let mut out = format!("input: {:?}\n", src);
+    let tokens = match lexer::tokenize(src, file) {
+        Ok(tokens) => tokens,
+        Err(errors) => {
+            for error in errors {
+                let _ = writeln!(out, "lex error {}..{} {}", error.span.start, error.span.end, error.details);
+            }
+            return out;
+        }
+    };
+    let mut stream = ParseStream::new(tokens, src);
+    let (ast, errors) = parser::statements(&mut stream);
+    let outline = Outline { src };
+    for stmt in &ast {
+        let _ = writeln!(out, "{}", outline.statement(stmt));
+    }
+    for error in errors {
+        let _ = write!(out, "error {}..{} {}", error.span.start, error.span.end, error.details);
+        // Errors that aren't about an unexpected token don't expect anything.
+        if !error.expected.is_empty() {
+            let found = error.found.map_or(String::from("end of input"), |kind| format!("{:?}", kind));
+            let _ = write!(out, " found {} expected {:?}", found, error.expected);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/parser").join(format!("{}.snap", name))
+}
+
+#[test]
+fn snapshots() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut failures = vec![];
+    for (name, src) in CASES {
+        let actual = render(src);
+        let path = snapshot_path(name);
+        if update {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!("{}:\n--- expected\n{}--- actual\n{}", name, expected, actual)),
+            Err(_) => failures.push(format!("{}: missing snapshot {}", name, path.display())),
+        }
+    }
+    assert!(failures.is_empty(), "parser snapshots differ (run with UPDATE_SNAPSHOTS=1 to accept):\n\n{}", failures.join("\n"));
+}
+
+#[test]
+fn program_returns_every_error() {
+    let mut sources = SourceMap::new();
+    let file = sources.add("main.cir", "fun main() {}\nfun 1() {}\n2 +;");
+    let mut stream = ParseStream::new(lexer::tokenize(sources.src(file), file).unwrap(), sources.src(file));
+    let errors = parser::program(&mut stream).unwrap_err();
+    let lines: Vec<(usize, &str)> = errors.iter().map(|error| (sources.start(error.span).line, error.details.as_str())).collect();
+    assert_eq!(lines, [(1, "Expected identifier."), (2, "Expected an expression.")]);
+}
+
+#[test]
+fn parse_file_returns_every_error() {
+    let mut sources = SourceMap::new();
+    let file = sources.add("main.cir", "fun main() { print(1 + 2); }\nfun other() {}");
+    assert_eq!(circuit::parse_file(&sources, file).unwrap().len(), 2);
+    let file = sources.add("broken.cir", "f(;\n1 +;");
+    let diagnostics = circuit::parse_file(&sources, file).unwrap_err();
+    let lines: Vec<usize> = diagnostics.iter().map(|diagnostic| sources.start(diagnostic.primary.span).line).collect();
+    assert_eq!(lines, [0, 1]);
+}
+
+#[test]
+fn parse_source_renders_errors() {
+    assert_eq!(circuit::parse_source("fun main() {}\nfun other() {}").unwrap().len(), 2);
+    let rendered = circuit::parse_source("let x = 1;\nlet = 2;").unwrap_err();
+    assert!(rendered.starts_with("error: Expected identifier.\n --> <source>:2:5\n"), "{}", rendered);
+}
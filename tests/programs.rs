@@ -0,0 +1,179 @@
+//! Program tests. Each case is a file under `tests/programs` that is run by both the interpreter
+//! and the VM, which have to agree. Any warnings, then what the program printed, then the result or
+//! the rendered errors are compared with `tests/snapshots/programs/<name>.snap`. Run with `UPDATE_SNAPSHOTS=1` to rewrite
+//! the snapshots. The other tests call into programs the way an embedder would.
+
+extern crate circuit_lang as circuit;
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use circuit::diagnostics::Diagnostic;
+use circuit::source_map::SourceMap;
+use circuit::{compiler, interp, modules, vm};
+
+const CASES: &[&str] = &[
+    "expressions",
+    "no_main",
+    "division_by_zero",
+    "underflow",
+    "type_mismatch",
+    "call_non_function",
+    "environments",
+    "parameters",
+    "wrong_arity",
+    "returns",
+    "blocks",
+    "if_else",
+    "missing_else",
+    "loops",
+    "iterate_uint",
+    "assignment",
+    "immutable_assignment",
+    "short_circuit",
+    "compare_types",
+    "bools",
+    "numbers",
+    "integer_overflow",
+    "strings",
+    "unknown_characters",
+    "line_endings",
+    "recursion",
+    "stack_overflow",
+    "nested_stack_overflow",
+    "trailing_block",
+    "property_assignment",
+    "negative_literals",
+    "negative_literal_range",
+    "mixed_literals",
+    "mixed_underflow",
+    "unchecked",
+    "printf",
+    "captures",
+];
+
+/// Collects what a program prints, so it can be read back after the backend that owns it is done.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+fn render(name: &str) -> String {
+    // Relative to the package root, so file names in the snapshots don't depend on the checkout.
+    let path = Path::new("tests/programs").join(format!("{}.cir", name));
+    let mut sources = SourceMap::new();
+    let file = sources.load(&path).unwrap();
+    let graph = match modules::load(&mut sources, file) {
+        Ok(graph) => graph,
+        Err(diagnostics) => return diagnostics.iter().map(|diagnostic| diagnostic.render(&sources, false)).collect(),
+    };
+    let warnings: String = graph.warnings().iter().map(|warning| warning.render(&sources, false)).collect();
+    let interpreter_output = Output::default();
+    let mut interpreter = interp::Interpreter::new(&graph, &sources);
+    interpreter.set_output(Box::new(interpreter_output.clone()));
+    let interpreted = interpreter.run().map_err(|error| Diagnostic::from(&error).render(&sources, false));
+    let program = match compiler::compile(&graph, &sources) {
+        Ok(program) => program,
+        Err(errors) => {
+            // The interpreter only finds the first of these, once it runs into it.
+            let first = interpreted.expect_err("the compiler failed but the interpreter succeeded");
+            assert_eq!(first, Diagnostic::from(&errors[0]).render(&sources, false), "{}", name);
+            return warnings + &errors.iter().map(|error| Diagnostic::from(error).render(&sources, false)).collect::<String>();
+        }
+    };
+    let vm_output = Output::default();
+    let mut machine = vm::Vm::new();
+    machine.set_output(Box::new(vm_output.clone()));
+    let compiled = machine.run(program).map_err(|error| Diagnostic::from(&error).render(&sources, false));
+    let printed = interpreter_output.text();
+    assert_eq!(printed, vm_output.text(), "{}: the backends printed different output", name);
+    match (interpreted, compiled) {
+        (Ok(a), Ok(b)) if a.to_string() == b.to_string() => format!("{}{}result: {}\n", warnings, printed, a),
+        (Err(a), Err(b)) if a == b => warnings + &printed + &a,
+        (a, b) => panic!("{}: the interpreter gave {:?} but the VM gave {:?}", name, a.map(|v| v.to_string()), b.map(|v| v.to_string())),
+    }
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/programs").join(format!("{}.snap", name))
+}
+
+#[test]
+fn snapshots() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut failures = vec![];
+    for name in CASES {
+        let actual = render(name);
+        let path = snapshot_path(name);
+        if update {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!("{}:\n--- expected\n{}--- actual\n{}", name, expected, actual)),
+            Err(_) => failures.push(format!("{}: missing snapshot {}", name, path.display())),
+        }
+    }
+    assert!(failures.is_empty(), "program snapshots differ (run with UPDATE_SNAPSHOTS=1 to accept):\n\n{}", failures.join("\n"));
+}
+
+const EMBEDDED: &str = "fun twice() { return 2 * 21; }\nfun divide(a, b) { return a / b; }";
+
+fn load_embedded() -> (SourceMap, modules::ModuleGraph) {
+    let mut sources = SourceMap::new();
+    let file = sources.add("embedded.cir", EMBEDDED);
+    let graph = modules::load(&mut sources, file).unwrap_or_else(|_| panic!("embedded program failed to load"));
+    (sources, graph)
+}
+
+#[test]
+fn vm_calls_globals_with_arguments() {
+    let (sources, graph) = load_embedded();
+    let program = compiler::compile(&graph, &sources).unwrap();
+    let mut machine = vm::Vm::new();
+    machine.execute(program).unwrap();
+    assert!(matches!(machine.global("twice"), Some(vm::Value::Function(_))));
+    assert_eq!(machine.call("twice", vec![]).unwrap(), vm::Value::UInt(42));
+    assert_eq!(machine.call("divide", vec![vm::Value::UInt(4), vm::Value::UInt(2)]).unwrap(), vm::Value::UInt(2));
+    assert_eq!(machine.call("divide", vec![vm::Value::UInt(1), vm::Value::UInt(0)]).unwrap_err().details, "Division by zero.");
+    assert_eq!(machine.call("missing", vec![]).unwrap_err().details, "Undefined name `missing`.");
+    assert_eq!(machine.call("twice", vec![vm::Value::UInt(1)]).unwrap_err().details, "Function `twice` takes 0 arguments but 1 were given.");
+    assert_eq!(machine.call("print", vec![vm::Value::String("from the host".into())]).unwrap(), vm::Value::Unit);
+    let too_many = vec![vm::Value::UInt(0); 256];
+    assert_eq!(machine.call("twice", too_many).unwrap_err().details, "Cannot pass 256 arguments to `twice`, the limit is 255.");
+    // A failed call leaves the globals usable.
+    assert_eq!(machine.call("twice", vec![]).unwrap(), vm::Value::UInt(42));
+}
+
+#[test]
+fn interpreter_calls_globals_with_arguments() {
+    let (sources, graph) = load_embedded();
+    let mut interpreter = interp::Interpreter::new(&graph, &sources);
+    interpreter.execute().unwrap();
+    assert!(matches!(interpreter.global("twice"), Some(interp::Value::Function(_))));
+    assert_eq!(interpreter.call("twice", vec![]).unwrap(), interp::Value::UInt(42));
+    assert_eq!(interpreter.call("divide", vec![interp::Value::UInt(4), interp::Value::UInt(2)]).unwrap(), interp::Value::UInt(2));
+    assert_eq!(interpreter.call("divide", vec![interp::Value::UInt(1), interp::Value::UInt(0)]).unwrap_err().details, "Division by zero.");
+    assert_eq!(interpreter.call("missing", vec![]).unwrap_err().details, "Undefined name `missing`.");
+    assert_eq!(interpreter.call("twice", vec![interp::Value::UInt(1)]).unwrap_err().details, "Function `twice` takes 0 arguments but 1 were given.");
+    assert_eq!(interpreter.call("print", vec![interp::Value::String("from the host".into())]).unwrap(), interp::Value::Unit);
+    assert_eq!(interpreter.call("twice", vec![]).unwrap(), interp::Value::UInt(42));
+}
@@ -0,0 +1,119 @@
+//! Property tests for spans over arbitrary Unicode source. Spans are byte ranges, so every span has
+//! to land on char boundaries, and the line and both columns the source map looks up have to agree
+//! with the text before it.
+
+extern crate circuit_lang as circuit;
+
+use circuit::lexer;
+use circuit::source_map::{SourceFile, SourceMap};
+use circuit::span::{FileId, FileIndex, Span};
+use proptest::prelude::*;
+use unicode_xid::UnicodeXID;
+
+// Where `index` should be, recomputed from the source by hand.
+fn expected(src: &str, index: usize) -> FileIndex {
+    let before = &src[..index];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let line = &before[line_start..];
+    FileIndex {
+        index,
+        line: before.matches('\n').count(),
+        column: line.chars().count(),
+        utf16_column: line.chars().map(char::len_utf16).sum(),
+    }
+}
+
+fn check_span(sources: &SourceMap, file: FileId, span: Span) -> Result<(), TestCaseError> {
+    let src = sources.src(file);
+    let range = span.range();
+    prop_assert_eq!(span.file, file);
+    prop_assert!(range.start <= range.end && range.end <= src.len(), "{:?} out of range", span);
+    prop_assert!(src.is_char_boundary(range.start) && src.is_char_boundary(range.end), "{:?} splits a char", span);
+    prop_assert_eq!(sources.start(span), expected(src, range.start));
+    prop_assert_eq!(sources.end(span), expected(src, range.end));
+    Ok(())
+}
+
+fn spans(src: &str, file: FileId) -> Vec<Span> {
+    match lexer::tokenize(src, file) {
+        Ok(tokens) => tokens.map(|tok| tok.span()).collect(),
+        Err(errors) => errors.iter().map(|error| error.span).collect(),
+    }
+}
+
+// Source that mixes code, multibyte text and line breaks, so it lexes further than pure noise.
+fn source() -> impl Strategy<Value = String> {
+    let piece = prop_oneof![
+        any::<char>().prop_map(String::from),
+        "[a-z_]{1,4}",
+        "[0-9]{1,3}",
+        Just(String::from("\n")),
+        Just(String::from("\r\n")),
+        Just(String::from(" ")),
+        Just(String::from("\"")),
+        Just(String::from("{")),
+        Just(String::from("}")),
+        Just(String::from("//")),
+        Just(String::from("/*")),
+        Just(String::from("*/")),
+        "[αβγ日本語😀é\u{301}\u{a0}\u{2028}]{1,3}",
+    ];
+    prop::collection::vec(piece, 0..40).prop_map(|pieces| pieces.concat())
+}
+
+fn ident() -> impl Strategy<Value = String> {
+    let start = any::<char>().prop_filter("xid start", |c| c.is_xid_start());
+    let rest = prop::collection::vec(any::<char>().prop_filter("xid continue", |c| c.is_xid_continue()), 0..6);
+    (start, rest).prop_map(|(start, rest)| std::iter::once(start).chain(rest).collect())
+}
+
+proptest! {
+    #[test]
+    fn spans_match_source(src in source()) {
+        let mut sources = SourceMap::new();
+        sources.add("padding", "\n");
+        let file = sources.add("case", src.as_str());
+        for span in spans(&src, file) {
+            check_span(&sources, file, span)?;
+        }
+    }
+
+    #[test]
+    fn locations_match_source(src in source()) {
+        let file = SourceFile::new("case", src.as_str());
+        for index in (0..=src.len()).filter(|&index| src.is_char_boundary(index)) {
+            prop_assert_eq!(file.location(index), expected(&src, index));
+        }
+    }
+
+    #[test]
+    fn parsing_never_panics(src in source()) {
+        let mut sources = SourceMap::new();
+        let file = sources.add("case", src);
+        let _ = circuit::parse_file(&sources, file);
+    }
+
+    #[test]
+    fn identifiers_round_trip(names in prop::collection::vec(ident(), 1..5), sep in "[ \t\n\u{a0}\u{3000}]{1,2}") {
+        let mut sources = SourceMap::new();
+        let file = sources.add("case", names.join(&sep));
+        let tokens: Vec<_> = lexer::tokenize(sources.src(file), file).unwrap().collect();
+        prop_assert_eq!(tokens.len(), names.len());
+        for (tok, name) in tokens.iter().zip(&names) {
+            check_span(&sources, file, tok.span())?;
+            prop_assert_eq!(sources.span_str(tok.span()), name.as_str());
+        }
+    }
+
+    #[test]
+    fn strings_round_trip(text in "[^\"\\\\{}]*") {
+        let mut sources = SourceMap::new();
+        let file = sources.add("case", format!("x = \"{}\";", text));
+        let tokens: Vec<_> = lexer::tokenize(sources.src(file), file).unwrap().collect();
+        prop_assert_eq!(tokens.len(), 4);
+        let span = tokens[2].span();
+        check_span(&sources, file, span)?;
+        prop_assert_eq!(sources.span_str(span), format!("\"{}\"", text));
+        check_span(&sources, file, tokens[3].span())?;
+    }
+}